@@ -0,0 +1,413 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    artwork::fetch_square_cover,
+    config::Config,
+    downloaders::replace_illegal_characters,
+    models::music::{Album, Song},
+};
+
+/// Names the cover is saved under, for players looking for either.
+static COVER_FILES: [&str; 2] = ["cover.jpg", "folder.jpg"];
+static METADATA_FILE: &str = "album.json";
+
+pub type JobId = u32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unable to write the album metadata: {0}")]
+    Metadata(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Place of a song in an album job.
+#[derive(TS, Debug, Clone, Copy, Serialize, Deserialize)]
+#[ts(export)]
+pub struct JobTrack {
+    pub job: JobId,
+    /// Position of the song in the album, from 0.
+    pub index: u32,
+}
+
+/// Album of a job along with its songs, in order, from which the job starts
+/// when the first of them is requested.
+#[derive(TS, Debug, Clone, Serialize, Deserialize)]
+#[ts(export)]
+pub struct JobAlbum {
+    pub album: Album,
+    pub songs: Vec<Song>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TrackState {
+    Pending,
+    Written(PathBuf),
+    /// Failed to download, it may still be downloaded again.
+    Failed,
+    /// Removed from the queue, it will not be downloaded.
+    Skipped,
+}
+
+/// Songs of an album or playlist downloaded together into their own folder.
+#[derive(Debug)]
+struct AlbumJob {
+    album: Album,
+    songs: Vec<Song>,
+    states: Vec<TrackState>,
+}
+
+/// Every album job, completed as their songs finish downloading.
+#[derive(Debug, Default)]
+pub struct AlbumJobs {
+    jobs: Mutex<HashMap<JobId, AlbumJob>>,
+    next_id: AtomicU32,
+}
+
+/// Contents of `album.json`.
+#[derive(Serialize)]
+struct AlbumMetadata<'a> {
+    album: &'a Album,
+    tracks: Vec<TrackMetadata<'a>>,
+}
+
+#[derive(Serialize)]
+struct TrackMetadata<'a> {
+    #[serde(flatten)]
+    song: &'a Song,
+    /// Path of the file relative to the album folder, if it was written.
+    file: Option<String>,
+}
+
+impl AlbumJobs {
+    pub fn new() -> Self {
+        AlbumJobs::default()
+    }
+
+    /// Reserves the id of a job, which only starts once one of its songs is
+    /// requested, see [AlbumJobs::start].
+    pub fn reserve(&self) -> JobId {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Starts the job `id` for the songs of `album`, unless it already is.
+    pub fn start(&self, id: JobId, album: JobAlbum) {
+        self.jobs
+            .lock()
+            .unwrap()
+            .entry(id)
+            .or_insert_with(|| AlbumJob {
+                states: vec![TrackState::Pending; album.songs.len()],
+                album: album.album,
+                songs: album.songs,
+            });
+    }
+
+    /// Drops the jobs no song of which is still pending, once the queue is
+    /// cleared their failed songs can no longer be retried.
+    pub fn drop_settled(&self) {
+        self.jobs
+            .lock()
+            .unwrap()
+            .retain(|_, job| job.states.contains(&TrackState::Pending));
+    }
+
+    /// Name of the folder the songs of `job` are downloaded into.
+    pub fn folder(&self, job: JobId) -> Option<String> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(&job)
+            .map(|job| folder_name(&job.album))
+    }
+
+    /// Records the outcome of a song, `path` being `None` when it failed.
+    ///
+    /// Once every song of the job has an outcome, the cover, the playlist and
+    /// the metadata are written in the album folder. Songs retried afterwards
+    /// write them again.
+    pub async fn complete(
+        &self,
+        track: JobTrack,
+        path: Option<PathBuf>,
+        config: &Config,
+    ) -> Result<()> {
+        let state = match path {
+            Some(path) => TrackState::Written(path),
+            None => TrackState::Failed,
+        };
+
+        self.record(track, state, config).await
+    }
+
+    /// Records that a song was removed from the queue, so the job completes
+    /// without it.
+    pub async fn skip(&self, track: JobTrack, config: &Config) -> Result<()> {
+        self.record(track, TrackState::Skipped, config).await
+    }
+
+    async fn record(&self, track: JobTrack, new_state: TrackState, config: &Config) -> Result<()> {
+        let (album, entries) = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let Some(job) = jobs.get_mut(&track.job) else {
+                return Ok(());
+            };
+            let Some(state) = job.states.get_mut(track.index as usize) else {
+                return Ok(());
+            };
+
+            // A written song stays in the album even if removed afterwards
+            if new_state == TrackState::Skipped && matches!(state, TrackState::Written(_)) {
+                return Ok(());
+            }
+            *state = new_state;
+
+            if job.states.contains(&TrackState::Pending) {
+                return Ok(());
+            }
+
+            let entries: Vec<_> = job
+                .songs
+                .iter()
+                .cloned()
+                .zip(job.states.iter().map(|state| match state {
+                    TrackState::Written(path) => Some(path.clone()),
+                    _ => None,
+                }))
+                .collect();
+            let album = job.album.clone();
+
+            // Failed songs may be retried into the folder, the others are done
+            if !job.states.contains(&TrackState::Failed) {
+                jobs.remove(&track.job);
+            }
+
+            (album, entries)
+        };
+
+        write_album_files(&album, &entries, config).await
+    }
+}
+
+/// Writes the cover, the playlist and the metadata of a finished album next
+/// to its songs.
+async fn write_album_files(
+    album: &Album,
+    entries: &[(Song, Option<PathBuf>)],
+    config: &Config,
+) -> Result<()> {
+    // Every song failed, there is no folder to complete
+    let Some(directory) = entries
+        .iter()
+        .find_map(|(_, path)| path.as_deref().and_then(Path::parent))
+    else {
+        return Ok(());
+    };
+
+    // A missing cover should not prevent writing the rest
+    if let Ok(cover) = fetch_square_cover(&album.cover_url, config.cover_size).await {
+        for file in COVER_FILES {
+            tokio::fs::write(directory.join(file), &cover).await?;
+        }
+    }
+
+    let playlist_path = directory.join(format!("{}.m3u8", folder_name(album)));
+    tokio::fs::write(playlist_path, playlist(entries, directory)).await?;
+
+    if config.album_folders.write_metadata {
+        let metadata = AlbumMetadata {
+            album,
+            tracks: entries
+                .iter()
+                .map(|(song, path)| TrackMetadata {
+                    song,
+                    file: path.as_deref().map(|path| relative_path(path, directory)),
+                })
+                .collect(),
+        };
+
+        tokio::fs::write(
+            directory.join(METADATA_FILE),
+            serde_json::to_vec_pretty(&metadata)?,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Writes an extended M3U playlist of the written songs, in album order.
+fn playlist(entries: &[(Song, Option<PathBuf>)], directory: &Path) -> String {
+    let mut playlist = String::from("#EXTM3U\n");
+
+    for (song, path) in entries {
+        let Some(path) = path else {
+            continue;
+        };
+        let duration = song.duration.map_or(-1, |duration| duration as i64);
+
+        playlist.push_str(&format!(
+            "#EXTINF:{duration},{} - {}\n{}\n",
+            song.artist,
+            song.title,
+            relative_path(path, directory)
+        ));
+    }
+
+    playlist
+}
+
+fn relative_path(path: &Path, directory: &Path) -> String {
+    path.strip_prefix(directory)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Folder of an album, named after its artist and title.
+fn folder_name(album: &Album) -> String {
+    let name = match &album.artist {
+        Some(artist) => format!("{artist} - {}", album.title),
+        None => album.title.clone(),
+    };
+
+    replace_illegal_characters(&name)
+        .trim()
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{models::music::SourceDownloader, testing::temp_directory};
+
+    use super::*;
+
+    fn song(title: &str, duration: Option<u32>) -> Song {
+        Song {
+            source: SourceDownloader::Deezer,
+            id: title.to_string(),
+            title: title.to_string(),
+            artist: "Artist".to_string(),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn writes_written_songs_in_order() {
+        let directory = Path::new("/music/Artist - Album");
+        let entries = [
+            (song("First", Some(180)), Some(directory.join("First.mp3"))),
+            (song("Missing", Some(60)), None),
+            (song("Second", None), Some(directory.join("Second.mp3"))),
+        ];
+
+        assert_eq!(
+            playlist(&entries, directory),
+            "#EXTM3U\n\
+             #EXTINF:180,Artist - First\nFirst.mp3\n\
+             #EXTINF:-1,Artist - Second\nSecond.mp3\n"
+        );
+    }
+
+    #[test]
+    fn names_folders_after_the_album() {
+        let album = Album {
+            title: "Back in Black".to_string(),
+            artist: Some("AC/DC".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(folder_name(&album), "ACDC - Back in Black");
+
+        let playlist = Album {
+            title: "Mix...".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(folder_name(&playlist), "Mix");
+    }
+
+    #[tokio::test]
+    async fn waits_for_every_song() {
+        let directory = temp_directory("album-job");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+
+        let jobs = AlbumJobs::new();
+        let album = Album {
+            title: "Album".to_string(),
+            ..Default::default()
+        };
+        let job = jobs.reserve();
+        assert!(jobs.folder(job).is_none());
+
+        let songs = vec![song("First", None), song("Second", None)];
+        jobs.start(job, JobAlbum { album, songs });
+        let playlist_path = directory.join("Album.m3u8");
+        let _ = tokio::fs::remove_file(&playlist_path).await;
+
+        let first = JobTrack { job, index: 0 };
+        let path = Some(directory.join("First.mp3"));
+        jobs.complete(first, path, &Config::default())
+            .await
+            .unwrap();
+        assert!(!playlist_path.exists());
+
+        let second = JobTrack { job, index: 1 };
+        jobs.complete(second, None, &Config::default())
+            .await
+            .unwrap();
+        assert!(playlist_path.exists());
+        // The failed song may be retried into the folder
+        assert!(jobs.folder(job).is_some());
+
+        jobs.skip(second, &Config::default()).await.unwrap();
+        assert!(jobs.folder(job).is_none());
+
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn drops_settled_jobs() {
+        let jobs = AlbumJobs::new();
+        let album = Album {
+            title: "Album".to_string(),
+            ..Default::default()
+        };
+        let settled = jobs.reserve();
+        let pending = jobs.reserve();
+        for job in [settled, pending] {
+            let songs = vec![song("First", None)];
+            jobs.start(
+                job,
+                JobAlbum {
+                    album: album.clone(),
+                    songs,
+                },
+            );
+        }
+
+        let track = JobTrack {
+            job: settled,
+            index: 0,
+        };
+        jobs.complete(track, None, &Config::default())
+            .await
+            .unwrap();
+        jobs.drop_settled();
+
+        assert!(jobs.folder(settled).is_none());
+        assert!(jobs.folder(pending).is_some());
+    }
+}
@@ -0,0 +1,131 @@
+use std::io::Cursor;
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GrayImage, ImageError};
+
+use crate::http;
+
+/// Luma under which a pixel is considered part of a black bar.
+static BAR_LUMA_THRESHOLD: u8 = 24;
+/// Quality of the generated JPEG covers.
+static JPEG_QUALITY: u8 = 90;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unable to fetch the cover: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("unable to process the cover: {0}")]
+    Image(#[from] ImageError),
+}
+
+/// Downloads the image at `url` as is.
+pub async fn fetch_cover(url: &str) -> Result<Vec<u8>, Error> {
+    let bytes = http::client()
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    Ok(bytes.to_vec())
+}
+
+/// Downloads the image at `url` and turns it into a square JPEG cover.
+pub async fn fetch_square_cover(url: &str, size: u32) -> Result<Vec<u8>, Error> {
+    Ok(square_cover(&fetch_cover(url).await?, size)?)
+}
+
+/// Turns an image into a `size`x`size` JPEG.
+///
+/// Black bars around the picture are trimmed first, then the remaining
+/// picture is cropped around its centre.
+pub fn square_cover(bytes: &[u8], size: u32) -> Result<Vec<u8>, ImageError> {
+    let image = image::load_from_memory(bytes)?;
+
+    let (x, y, width, height) = content_bounds(&image.to_luma8());
+    let side = width.min(height);
+    let x = x + (width - side) / 2;
+    let y = y + (height - side) / 2;
+
+    let cover = image
+        .crop_imm(x, y, side, side)
+        .resize_exact(size, size, FilterType::Lanczos3);
+    let cover = DynamicImage::ImageRgb8(cover.to_rgb8());
+
+    let mut jpeg = Cursor::new(Vec::new());
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode_image(&cover)?;
+
+    Ok(jpeg.into_inner())
+}
+
+/// Finds the part of the image that is not a black bar, as `(x, y, width, height)`.
+fn content_bounds(image: &GrayImage) -> (u32, u32, u32, u32) {
+    let (width, height) = image.dimensions();
+    let is_bar_row = |y: u32| (0..width).all(|x| image.get_pixel(x, y)[0] <= BAR_LUMA_THRESHOLD);
+    let is_bar_column =
+        |x: u32| (0..height).all(|y| image.get_pixel(x, y)[0] <= BAR_LUMA_THRESHOLD);
+
+    let top = (0..height).find(|&y| !is_bar_row(y));
+    let Some(top) = top else {
+        // Fully black image, nothing to trim
+        return (0, 0, width, height);
+    };
+    let bottom = (0..height).rev().find(|&y| !is_bar_row(y)).unwrap_or(top);
+    let left = (0..width).find(|&x| !is_bar_column(x)).unwrap_or(0);
+    let right = (0..width)
+        .rev()
+        .find(|&x| !is_bar_column(x))
+        .unwrap_or(left);
+
+    (left, top, right - left + 1, bottom - top + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, Luma};
+
+    use super::*;
+
+    /// Creates a black image with a white rectangle at the given bounds.
+    fn framed_image(width: u32, height: u32, bounds: (u32, u32, u32, u32)) -> GrayImage {
+        let (x, y, w, h) = bounds;
+
+        GrayImage::from_fn(width, height, |px, py| {
+            if (x..x + w).contains(&px) && (y..y + h).contains(&py) {
+                Luma([255])
+            } else {
+                Luma([0])
+            }
+        })
+    }
+
+    #[test]
+    fn finds_content_inside_black_bars() {
+        let pillarboxed = framed_image(160, 90, (35, 0, 90, 90));
+        assert_eq!(content_bounds(&pillarboxed), (35, 0, 90, 90));
+
+        let letterboxed = framed_image(160, 90, (0, 10, 160, 70));
+        assert_eq!(content_bounds(&letterboxed), (0, 10, 160, 70));
+    }
+
+    #[test]
+    fn keeps_fully_black_images() {
+        let black = GrayImage::new(16, 9);
+
+        assert_eq!(content_bounds(&black), (0, 0, 16, 9));
+    }
+
+    #[test]
+    fn crops_to_a_square() {
+        let image = DynamicImage::ImageLuma8(framed_image(160, 90, (0, 0, 160, 90)));
+        let mut bytes = Cursor::new(Vec::new());
+        image
+            .write_to(&mut bytes, image::ImageFormat::Png)
+            .expect("Image should be encodable");
+
+        let cover = square_cover(bytes.get_ref(), 50).expect("Cover should be created");
+        let cover = image::load_from_memory(&cover).expect("Cover should be a valid image");
+
+        assert_eq!(cover.dimensions(), (50, 50));
+    }
+}
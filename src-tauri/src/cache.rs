@@ -0,0 +1,213 @@
+use std::{
+    io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{MetadataCacheSettings, MEGABYTE},
+    models::music::Resolution,
+    parsers::ParsedId,
+};
+
+static HOUR: u64 = 60 * 60;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unable to read the cache entry: {0}")]
+    Entry(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Resolutions of ids saved on disk, so resolving them again does not query
+/// their source.
+///
+/// Each id is saved in a file of its own. Expired entries are removed when
+/// read, and the oldest ones when the cache grows over its size.
+#[derive(Debug)]
+pub struct MetadataCache {
+    directory: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    /// Seconds since the Unix epoch.
+    saved_at: u64,
+    resolution: Resolution,
+}
+
+impl MetadataCache {
+    pub fn new(directory: PathBuf) -> Self {
+        MetadataCache { directory }
+    }
+
+    /// The saved resolution of `id`, if it has not expired.
+    pub async fn get(&self, id: &ParsedId, settings: &MetadataCacheSettings) -> Option<Resolution> {
+        if !settings.enabled {
+            return None;
+        }
+
+        let path = self.path(id);
+        let bytes = tokio::fs::read(&path).await.ok()?;
+
+        match serde_json::from_slice::<Entry>(&bytes) {
+            Ok(entry) if !is_expired(entry.saved_at, now(), settings.ttl) => Some(entry.resolution),
+            // Expired or written by an older version
+            _ => {
+                let _ = tokio::fs::remove_file(&path).await;
+                None
+            }
+        }
+    }
+
+    /// Saves the resolution of `id`, then removes the oldest entries if the
+    /// cache got too big.
+    pub async fn insert(
+        &self,
+        id: &ParsedId,
+        resolution: &Resolution,
+        settings: &MetadataCacheSettings,
+    ) -> Result<()> {
+        if !settings.enabled {
+            return Ok(());
+        }
+
+        let entry = Entry {
+            saved_at: now(),
+            resolution: resolution.clone(),
+        };
+
+        tokio::fs::create_dir_all(&self.directory).await?;
+        tokio::fs::write(self.path(id), serde_json::to_vec(&entry)?).await?;
+
+        self.shrink(settings.max_size as u64 * MEGABYTE).await
+    }
+
+    /// Removes every entry.
+    pub async fn clear(&self) -> Result<()> {
+        match tokio::fs::remove_dir_all(&self.directory).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the oldest entries until the cache takes at most `max_size`
+    /// bytes.
+    async fn shrink(&self, max_size: u64) -> Result<()> {
+        let mut files = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.directory).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((entry.path(), metadata.len(), modified));
+            }
+        }
+
+        for path in oldest_over_size(files, max_size) {
+            // Another download may have removed it already
+            let _ = tokio::fs::remove_file(path).await;
+        }
+
+        Ok(())
+    }
+
+    fn path(&self, id: &ParsedId) -> PathBuf {
+        self.directory.join(format!("{}.json", cache_key(id)))
+    }
+}
+
+/// Name of the entry of `id`, unique across sources.
+fn cache_key(id: &ParsedId) -> String {
+    match id {
+        ParsedId::DeezerAlbum(id) => format!("deezer-album-{id}"),
+        ParsedId::DeezerTrack(id) => format!("deezer-track-{id}"),
+        // Youtube ids only contain letters, digits, `-` and `_`
+        ParsedId::YoutubeVideo(id) => format!("youtube-video-{id}"),
+        ParsedId::YoutubePlaylist(id) => format!("youtube-playlist-{id}"),
+    }
+}
+
+/// Whether an entry saved at `saved_at` is older than `ttl` hours at `now`.
+fn is_expired(saved_at: u64, now: u64, ttl: u32) -> bool {
+    now.saturating_sub(saved_at) >= ttl as u64 * HOUR
+}
+
+/// Files to remove, oldest first, so the rest takes at most `max_size` bytes.
+fn oldest_over_size(mut files: Vec<(PathBuf, u64, SystemTime)>, max_size: u64) -> Vec<PathBuf> {
+    let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort_by_key(|(_, _, modified)| *modified);
+
+    files
+        .into_iter()
+        .take_while(|(_, len, _)| {
+            let over = size > max_size;
+            size -= len;
+            over
+        })
+        .map(|(path, _, _)| path)
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::temp_directory;
+
+    use super::*;
+
+    #[test]
+    fn expires_entries_after_their_ttl() {
+        assert!(!is_expired(0, HOUR - 1, 1));
+        assert!(is_expired(0, HOUR, 1));
+        assert!(is_expired(0, 0, 0));
+    }
+
+    #[test]
+    fn removes_the_oldest_files_first() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let files = vec![
+            (PathBuf::from("new"), 40, at(3)),
+            (PathBuf::from("old"), 40, at(1)),
+            (PathBuf::from("middle"), 40, at(2)),
+        ];
+
+        assert_eq!(
+            oldest_over_size(files.clone(), 80),
+            vec![PathBuf::from("old")]
+        );
+        assert!(oldest_over_size(files, 120).is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_resolutions_until_cleared() {
+        let directory = temp_directory("metadata-cache");
+        let cache = MetadataCache::new(directory.clone());
+        let settings = MetadataCacheSettings::default();
+        let id = ParsedId::DeezerAlbum(42);
+
+        cache
+            .insert(&id, &Resolution::default(), &settings)
+            .await
+            .unwrap();
+        assert!(cache.get(&id, &settings).await.is_some());
+
+        cache.clear().await.unwrap();
+        assert!(cache.get(&id, &settings).await.is_none());
+
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+}
@@ -2,7 +2,11 @@ use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 use ts_rs::TS;
 
-#[derive(TS, Debug, Serialize, Deserialize, Clone, Default, Display)]
+/// Bytes in a megabyte, the unit of the size settings.
+pub static MEGABYTE: u64 = 1024 * 1024;
+
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Default, Display, PartialEq, Eq)]
+#[ts(export)]
 #[strum(serialize_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum YoutubeFormat {
@@ -11,12 +15,366 @@ pub enum YoutubeFormat {
     WEBM,
     WAV,
     OGG,
+    FLAC,
+    M4A,
+    OPUS,
+    /// The audio stream as served by Youtube, without transcoding.
+    ORIGINAL,
+}
+
+impl YoutubeFormat {
+    /// Bitrate files are expected to have in this format, in kbps.
+    pub fn estimated_bitrate(&self, encoding: &AudioEncoding) -> u32 {
+        match self {
+            YoutubeFormat::WAV => 1411,
+            YoutubeFormat::FLAC => 1000,
+            // Youtube serves audio at up to 160kbps
+            YoutubeFormat::ORIGINAL => 160,
+            _ => encoding.bitrate.unwrap_or(192),
+        }
+    }
 }
 
+/// Encoder settings of Youtube downloads, each left to the encoder when unset.
 #[derive(TS, Debug, Serialize, Deserialize, Clone, Default)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioEncoding {
+    /// Constant bitrate, in kbps. Ignored by lossless formats.
+    pub bitrate: Option<u32>,
+    /// Variable bitrate quality, from 0 (best) to 9, used instead of the
+    /// bitrate. Only MP3 and OGG support it.
+    pub vbr_quality: Option<u8>,
+    /// Sample rate, in Hz.
+    pub sample_rate: Option<u32>,
+    /// Number of channels.
+    pub channels: Option<u8>,
+}
+
+impl AudioEncoding {
+    /// Whether every setting is left to the encoder.
+    pub fn is_unset(&self) -> bool {
+        self.bitrate.is_none()
+            && self.vbr_quality.is_none()
+            && self.sample_rate.is_none()
+            && self.channels.is_none()
+    }
+}
+
+/// Audio quality of Deezer downloads, from lowest to highest.
+#[derive(
+    TS, Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum DeezerQuality {
+    #[default]
+    Mp3_128,
+    Mp3_320,
+    Flac,
+}
+
+impl DeezerQuality {
+    /// Bitrate files are expected to have in this quality, in kbps.
+    pub fn estimated_bitrate(&self) -> u32 {
+        match self {
+            DeezerQuality::Mp3_128 => 128,
+            DeezerQuality::Mp3_320 => 320,
+            DeezerQuality::Flac => 1000,
+        }
+    }
+
+    /// Extension of the files written in this quality.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DeezerQuality::Mp3_128 | DeezerQuality::Mp3_320 => "mp3",
+            DeezerQuality::Flac => "flac",
+        }
+    }
+}
+
+/// Tag the name of a playlist is written in, for songs resolved from one.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[ts(export)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistTag {
+    /// The playlist becomes the album of its songs, its owner the album artist.
+    #[default]
+    Album,
+    /// Songs keep their album, the playlist is written as their grouping.
+    Grouping,
+}
+
+/// EBU R128 loudness normalisation settings.
+#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessNormalization {
+    pub enabled: bool,
+    /// Integrated loudness to reach, in LUFS.
+    pub target_lufs: f32,
+    /// Maximum true peak, in dBTP.
+    pub true_peak: f32,
+    /// Also normalise Deezer downloads, which are otherwise left as is.
+    pub apply_to_deezer: bool,
+}
+
+impl Default for LoudnessNormalization {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_lufs: -14.0,
+            true_peak: -1.0,
+            apply_to_deezer: false,
+        }
+    }
+}
+
+/// Removal of the silence at the start and end of downloads.
+#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SilenceTrimming {
+    pub enabled: bool,
+    /// Volume under which audio is considered silent, in dB.
+    pub threshold_db: f32,
+    /// Shortest silence that gets trimmed, in seconds.
+    pub min_duration: f32,
+    /// Also trim Deezer downloads, which are otherwise left as is.
+    pub apply_to_deezer: bool,
+}
+
+impl Default for SilenceTrimming {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -50.0,
+            min_duration: 0.5,
+            apply_to_deezer: false,
+        }
+    }
+}
+
+/// Splitting of long videos without chapters at their silences.
+#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SilenceSplitting {
+    pub enabled: bool,
+    /// Volume under which audio is considered silent, in dB.
+    pub threshold_db: f32,
+    /// Shortest silence considered a gap between two tracks, in seconds.
+    pub min_duration: f32,
+}
+
+impl Default for SilenceSplitting {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -40.0,
+            min_duration: 1.5,
+        }
+    }
+}
+
+/// Lyrics added to downloads.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsSettings {
+    pub enabled: bool,
+    /// Also save synced lyrics as an `.lrc` file next to the downloaded file.
+    pub write_lrc: bool,
+}
+
+/// Checks of downloaded files before they are reported as finished.
+#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Verification {
+    pub enabled: bool,
+    /// Largest accepted difference between the durations of the file and the
+    /// song, in seconds.
+    pub duration_tolerance: f32,
+    /// Number of times an incomplete download is attempted again.
+    pub retries: u32,
+}
+
+impl Default for Verification {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            duration_tolerance: 3.0,
+            retries: 1,
+        }
+    }
+}
+
+/// Downloads of whole albums and playlists into a folder of their own.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumFolders {
+    /// Download albums into their own folder, with their cover and an `.m3u8`
+    /// playlist once every song is done.
+    pub enabled: bool,
+    /// Also save the resolved metadata as `album.json` in the folder.
+    pub write_metadata: bool,
+}
+
+/// Cache of the songs ids resolve to, saved on disk.
+#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataCacheSettings {
+    pub enabled: bool,
+    /// Time entries are kept for, in hours.
+    pub ttl: u32,
+    /// Size the cache is kept under, in MB.
+    pub max_size: u32,
+}
+
+impl Default for MetadataCacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl: 24,
+            max_size: 50,
+        }
+    }
+}
+
+/// Download rate limits, in bytes per second, each unlimited when unset.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthLimits {
+    /// Rate of every download combined.
+    pub global: Option<u32>,
+    pub deezer: Option<u32>,
+    pub youtube: Option<u32>,
+    /// Hours the limits apply in, the whole day when unset.
+    #[ts(inline)]
+    pub schedule: Option<LimitSchedule>,
+}
+
+/// Part of the day, in local time, as `HH:MM`. Schedules ending before they
+/// start wrap around midnight.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LimitSchedule {
+    pub start: String,
+    pub end: String,
+}
+
+/// Proxy every request goes through.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxySettings {
+    /// URL of the proxy, such as `http://proxy:3128` or `socks5://proxy:1080`.
+    /// Requests are sent directly when unset.
+    pub url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Hosts reached without the proxy, such as `localhost` or `.example.com`.
+    pub no_proxy: Vec<String>,
+}
+
+/// Client used by the requests the app makes itself.
+#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpSettings {
+    /// Time to wait for a connection to a server, in seconds.
+    pub connect_timeout: u32,
+    /// Time to wait for a server to send anything, in seconds.
+    pub read_timeout: u32,
+    /// Sent with every request, the client default when empty.
+    pub user_agent: String,
+    /// Sent with every request.
+    #[ts(inline)]
+    pub headers: Vec<HttpHeader>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 10,
+            read_timeout: 30,
+            user_agent: concat!("prawnloader/", env!("CARGO_PKG_VERSION")).to_string(),
+            headers: Vec::new(),
+        }
+    }
+}
+
+#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(TS, Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct Config {
     #[ts(inline)]
     pub youtube_format: YoutubeFormat,
+    pub youtube_encoding: AudioEncoding,
+    /// Width and height of the covers embedded in Youtube downloads, in pixels.
+    pub cover_size: u32,
+    /// Also save the cover next to the downloaded file, named after it.
+    pub save_cover_file: bool,
+    /// Split Youtube videos with chapters into one song per chapter.
+    pub split_chapters: bool,
+    #[ts(inline)]
+    pub playlist_tag: PlaylistTag,
+    #[ts(inline)]
+    pub loudness: LoudnessNormalization,
+    #[ts(inline)]
+    pub silence_trimming: SilenceTrimming,
+    #[ts(inline)]
+    pub silence_splitting: SilenceSplitting,
+    #[ts(inline)]
+    pub lyrics: LyricsSettings,
+    #[ts(inline)]
+    pub album_folders: AlbumFolders,
+    #[ts(inline)]
+    pub verification: Verification,
+    /// Number of Deezer songs downloaded at the same time.
+    pub deezer_threads: u32,
+    /// Number of Youtube songs downloaded at the same time.
+    pub youtube_threads: u32,
+    /// Maximum number of songs downloaded at the same time, all sources combined.
+    pub max_threads: u32,
+    /// Free space to keep in the download directory, in MB. Downloads pause
+    /// instead of going below it, 0 never checks.
+    pub min_free_space: u32,
+    #[ts(inline)]
+    pub metadata_cache: MetadataCacheSettings,
+    #[ts(inline)]
+    pub bandwidth: BandwidthLimits,
+    #[ts(inline)]
+    pub proxy: ProxySettings,
+    #[ts(inline)]
+    pub http: HttpSettings,
+    /// Directory containing ffmpeg and ffprobe, looked up in the `PATH` when unset.
+    pub ffmpeg_directory: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            youtube_format: YoutubeFormat::default(),
+            youtube_encoding: AudioEncoding::default(),
+            cover_size: 600,
+            save_cover_file: false,
+            split_chapters: false,
+            playlist_tag: PlaylistTag::default(),
+            loudness: LoudnessNormalization::default(),
+            silence_trimming: SilenceTrimming::default(),
+            silence_splitting: SilenceSplitting::default(),
+            lyrics: LyricsSettings::default(),
+            album_folders: AlbumFolders::default(),
+            verification: Verification::default(),
+            deezer_threads: 4,
+            youtube_threads: 4,
+            max_threads: 8,
+            min_free_space: 500,
+            metadata_cache: MetadataCacheSettings::default(),
+            bandwidth: BandwidthLimits::default(),
+            proxy: ProxySettings::default(),
+            http: HttpSettings::default(),
+            ffmpeg_directory: None,
+        }
+    }
 }
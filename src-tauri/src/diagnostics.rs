@@ -0,0 +1,151 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Serialize;
+use tauri::api::path::download_dir;
+use ts_rs::TS;
+
+use crate::{
+    downloaders::{SourceStatus, Sources},
+    ffmpeg,
+    models::music::SourceDownloader,
+};
+
+/// Name of the file written to check that the download directory is writable.
+static WRITE_CHECK_FILE: &str = ".prawnloader-write-check";
+
+/// State of everything downloads depend on.
+#[derive(TS, Debug, Clone, Serialize)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    pub ffmpeg: Tool,
+    pub ffprobe: Tool,
+    /// Encoders needed to transcode Youtube downloads.
+    pub encoders: Vec<EncoderSupport>,
+    pub download_directory: DownloadDirectory,
+    pub sources: HashMap<SourceDownloader, SourceCheck>,
+}
+
+/// An external program, when it was found.
+#[derive(TS, Debug, Clone, Serialize)]
+#[ts(export)]
+pub struct Tool {
+    pub path: Option<String>,
+    /// First line of its version output.
+    pub version: Option<String>,
+}
+
+#[derive(TS, Debug, Clone, Serialize)]
+#[ts(export)]
+pub struct EncoderSupport {
+    pub name: String,
+    pub available: bool,
+}
+
+#[derive(TS, Debug, Clone, Serialize)]
+#[ts(export)]
+pub struct DownloadDirectory {
+    pub path: Option<String>,
+    pub writable: bool,
+    /// Why the directory is not writable.
+    pub error: Option<String>,
+}
+
+#[derive(TS, Debug, Clone, Serialize)]
+#[ts(export)]
+pub struct SourceCheck {
+    pub status: SourceStatus,
+    /// Why the source is unable to download songs.
+    pub error: Option<String>,
+}
+
+/// Checks every prerequisite of downloads.
+pub async fn diagnose(sources: &Sources) -> Diagnostics {
+    let available_encoders = ffmpeg::encoders().await.unwrap_or_default();
+    let encoders = ffmpeg::required_encoders()
+        .into_iter()
+        .map(|name| EncoderSupport {
+            name: name.to_string(),
+            available: available_encoders.iter().any(|encoder| encoder == name),
+        })
+        .collect();
+
+    let checks = sources.check().await;
+    let statuses = sources.statuses();
+    let sources = checks
+        .into_iter()
+        .map(|(kind, check)| {
+            let source_check = SourceCheck {
+                status: statuses[&kind],
+                error: check.err().map(|err| err.to_string()),
+            };
+
+            (kind, source_check)
+        })
+        .collect();
+
+    Diagnostics {
+        ffmpeg: tool("ffmpeg").await,
+        ffprobe: tool("ffprobe").await,
+        encoders,
+        download_directory: download_directory().await,
+        sources,
+    }
+}
+
+async fn tool(binary: &'static str) -> Tool {
+    Tool {
+        path: ffmpeg::locate(binary).map(|path| path.to_string_lossy().into_owned()),
+        version: ffmpeg::version(binary).await,
+    }
+}
+
+async fn download_directory() -> DownloadDirectory {
+    let Some(directory) = download_dir() else {
+        return DownloadDirectory {
+            path: None,
+            writable: false,
+            error: Some("no download directory available".to_string()),
+        };
+    };
+
+    let error = check_writable(&directory).await.err();
+
+    DownloadDirectory {
+        path: Some(directory.to_string_lossy().into_owned()),
+        writable: error.is_none(),
+        error: error.map(|err| err.to_string()),
+    }
+}
+
+/// Writes then removes a file in `directory`.
+async fn check_writable(directory: &Path) -> std::io::Result<()> {
+    let path = directory.join(WRITE_CHECK_FILE);
+    tokio::fs::write(&path, b"").await?;
+    tokio::fs::remove_file(&path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::temp_directory;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn checks_writable_directory() {
+        let directory = temp_directory("writable-directory");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+
+        assert!(check_writable(&directory).await.is_ok());
+        assert!(!directory.join(WRITE_CHECK_FILE).exists());
+
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn fails_on_missing_directory() {
+        let directory = temp_directory("missing-directory");
+
+        assert!(check_writable(&directory).await.is_err());
+    }
+}
@@ -1,66 +1,172 @@
-use color_eyre::eyre::{eyre, Result};
-use crossbeam_channel::{unbounded, Sender};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use crossbeam_channel::Sender;
 use deezer::{models::Track, DeezerClient};
 use deezer_downloader::{
     song::{Album, Artist},
     Downloader as DeezerDownloader, SongMetadata,
 };
-use futures::future::join_all;
-use tauri::api::path::download_dir;
+use futures::{stream, StreamExt};
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+
+use crate::{
+    artwork::fetch_cover,
+    config::DeezerQuality,
+    http,
+    models::music::{self, Resolution, Song, SourceDownloader, UnresolvedSong},
+    parsers::ParsedId,
+    partial::PartialFile,
+    tagging::write_tags,
+};
 
-use crate::models::music::Song;
+use super::{
+    check_free_space, estimated_size, finish_verified, post_process, replace_illegal_characters,
+    throttle::Throttle, Capabilities, DownloadRequest, DownloadSummary, Error, ProgressEvent,
+    Result, Source, SourceStatus,
+};
 
-use super::{replace_illegal_characters, ProgressEvent};
+static INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+static MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
-static DOWNLOAD_THREADS: u64 = 4;
+/// Number of album tracks fetched at the same time.
+static ALBUM_TRACKS_CONCURRENCY: usize = 8;
+/// Number of times fetching a single album track is attempted.
+static TRACK_ATTEMPTS: u32 = 3;
 
-#[derive(Debug)]
-pub struct Downloader {
-    deezer_client: DeezerClient,
-    download_tx: Sender<Song>,
+/// Quality of every Deezer download.
+///
+/// The session is anonymous and Deezer only serves MP3 128 without a premium
+/// account, so there is no quality to choose.
+static QUALITY: DeezerQuality = DeezerQuality::Mp3_128;
+
+/// Deezer session shared by every download.
+///
+/// The session is only created when a download needs it, and creation is
+/// retried until it succeeds.
+struct Session {
+    downloader: Mutex<Option<Arc<DeezerDownloader>>>,
+    /// Set when the proxy or the HTTP settings change, the session keeps the
+    /// client it was created with.
+    outdated: AtomicBool,
+    status: StdMutex<SourceStatus>,
+    progress_tx: Sender<ProgressEvent>,
 }
 
-impl Downloader {
-    pub fn new(progress_tx: Sender<ProgressEvent>) -> Self {
-        let (download_tx, download_rx) = unbounded::<Song>();
-
-        for _ in 0..DOWNLOAD_THREADS {
-            let _download_rx = download_rx.clone();
-            let _progress_tx = progress_tx.clone();
-
-            tokio::spawn(async move {
-                let downloader = DeezerDownloader::new().await.unwrap();
-                while let Ok(song) = _download_rx.recv() {
-                    _progress_tx
-                        .send(ProgressEvent::Start(song.clone()))
-                        .unwrap();
-
-                    let result = download_song(song.clone(), &downloader).await;
-                    let progress = match result {
-                        Ok(_) => ProgressEvent::Finish(song),
-                        // FIXME: Add download error String
-                        Err(_) => ProgressEvent::DownloadError(song, String::new()),
-                    };
-
-                    _progress_tx.send(progress).unwrap();
-                }
-            });
+impl Session {
+    fn new(progress_tx: Sender<ProgressEvent>) -> Self {
+        Session {
+            downloader: Mutex::new(None),
+            outdated: AtomicBool::new(false),
+            status: StdMutex::new(SourceStatus::Offline),
+            progress_tx,
         }
+    }
 
-        Downloader {
-            deezer_client: DeezerClient::new(),
-            download_tx,
+    fn status(&self) -> SourceStatus {
+        *self.status.lock().unwrap()
+    }
+
+    fn set_status(&self, status: SourceStatus) {
+        let mut current = self.status.lock().unwrap();
+
+        if *current != status {
+            *current = status;
+            self.progress_tx
+                .send(ProgressEvent::SourceStatus(
+                    SourceDownloader::Deezer,
+                    status,
+                ))
+                .unwrap();
+        }
+    }
+
+    /// Returns the session, creating it first if needed.
+    ///
+    /// Waits for as long as the session cannot be created.
+    async fn get(&self) -> Arc<DeezerDownloader> {
+        let mut delay = INITIAL_RETRY_DELAY;
+
+        loop {
+            if let Ok(downloader) = self.try_get().await {
+                return downloader;
+            }
+
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
         }
     }
 
-    pub async fn request_download(&self, song: Song) -> Result<()> {
-        self.download_tx.send(song).expect("Channel should be open");
+    /// Returns the session, trying to create it once if needed.
+    async fn try_get(&self) -> Result<Arc<DeezerDownloader>> {
+        let mut downloader = self.downloader.lock().await;
+
+        if self.outdated.swap(false, Ordering::Relaxed) {
+            *downloader = None;
+        }
+
+        if let Some(downloader) = downloader.as_ref() {
+            return Ok(downloader.clone());
+        }
+
+        match DeezerDownloader::with_client(http::client()).await {
+            Ok(new_downloader) => {
+                let new_downloader = Arc::new(new_downloader);
+                *downloader = Some(new_downloader.clone());
 
-        Ok(())
+                // A degraded session stays so until a download succeeds
+                if self.status() == SourceStatus::Offline {
+                    self.set_status(SourceStatus::Ready);
+                }
+
+                Ok(new_downloader)
+            }
+            Err(_) => {
+                self.set_status(SourceStatus::Offline);
+
+                Err(Error::Deezer(
+                    "unable to create a Deezer session".to_string(),
+                ))
+            }
+        }
+    }
+
+    /// Drops the session after a failed download, so the next one starts
+    /// with a fresh session.
+    async fn invalidate(&self) {
+        *self.downloader.lock().await = None;
+        self.set_status(SourceStatus::Degraded);
+    }
+
+    /// Makes the next download create a session with the current proxy and
+    /// HTTP settings.
+    fn outdate(&self) {
+        self.outdated.store(true, Ordering::Relaxed);
+    }
+}
+
+pub struct Downloader {
+    session: Session,
+    throttle: Throttle,
+}
+
+impl Downloader {
+    pub fn new(progress_tx: Sender<ProgressEvent>, throttle: Throttle) -> Self {
+        Downloader {
+            session: Session::new(progress_tx),
+            throttle,
+        }
     }
 
     pub async fn get_track(&self, id: u64) -> Option<Track> {
-        let maybe_track = self.deezer_client.track(id).await;
+        let maybe_track = deezer_client().track(id).await;
 
         // Check if the song was found AND is readable
         match maybe_track {
@@ -69,61 +175,225 @@ impl Downloader {
         }
     }
 
-    pub async fn get_album_tracks(&self, id: u64) -> Option<Vec<Song>> {
-        let maybe_album = self.deezer_client.album(id).await;
-        if let Ok(Some(album)) = maybe_album {
-            let futures: Vec<_> = album
-                .tracks
-                .into_iter()
-                .map(|album_track| {
-                    async move {
-                        // FIXME: Expect may panic on unsable connections
-                        album_track
-                            .get_full()
-                            .await
-                            .expect("Track should always be available.")
-                            .into()
+    /// Fetches a track along with the details of its album.
+    pub async fn get_song(&self, id: u64) -> Option<Song> {
+        let track = self.get_track(id).await?;
+        let album = deezer_client().album(track.album.id).await;
+
+        let mut song = Song::from(track);
+        // The album only adds details, the song is still usable without it
+        if let Ok(Some(album)) = album {
+            song.album = music::Album::from(&album);
+        }
+
+        Some(song)
+    }
+
+    /// Resolves every track of an album.
+    ///
+    /// Tracks that cannot be fetched or are not readable are reported in
+    /// [Resolution::unresolved] instead of failing the whole album.
+    pub async fn get_album_tracks(&self, id: u64) -> Option<Resolution> {
+        let client = &deezer_client();
+        let Ok(Some(album)) = client.album(id).await else {
+            return None;
+        };
+
+        let album_details = music::Album::from(&album);
+        let results: Vec<_> = stream::iter(album.tracks)
+            .map(|album_track| async move {
+                let mut delay = INITIAL_RETRY_DELAY;
+                let mut attempt = 1;
+
+                let result = loop {
+                    match client.track(album_track.id).await {
+                        Ok(track) => break Ok(track),
+                        Err(_) if attempt < TRACK_ATTEMPTS => {
+                            tokio::time::sleep(delay).await;
+                            delay *= 2;
+                            attempt += 1;
+                        }
+                        Err(err) => break Err(err.to_string()),
                     }
-                })
-                .collect();
+                };
 
-            return Some(join_all(futures).await);
+                (album_track, result)
+            })
+            .buffered(ALBUM_TRACKS_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut resolution = Resolution {
+            album: Some(album_details.clone()),
+            ..Default::default()
+        };
+        for (album_track, result) in results {
+            let reason = match result {
+                Ok(Some(track)) if track.readable => {
+                    resolution.songs.push(Song {
+                        album: album_details.clone(),
+                        ..track.into()
+                    });
+                    continue;
+                }
+                Ok(Some(_)) => "Track is not readable.".to_string(),
+                Ok(None) => "Track not found.".to_string(),
+                Err(reason) => reason,
+            };
+
+            resolution.unresolved.push(UnresolvedSong {
+                id: album_track.id.to_string(),
+                title: album_track.title,
+                reason,
+            });
         }
 
-        None
+        Some(resolution)
     }
 }
 
-async fn download_song(song: Song, downloader: &DeezerDownloader) -> Result<()> {
-    let maybe_song =
-        deezer_downloader::Song::download_from_metadata(metadata_from_song(song), downloader).await;
-    let song = match maybe_song {
-        Ok(it) => it,
-        Err(_) => return Err(eyre!("Song not found.")),
-    };
+#[async_trait]
+impl Source for Downloader {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            albums: true,
+            playlists: false,
+            format_choice: false,
+        }
+    }
+
+    fn status(&self) -> SourceStatus {
+        self.session.status()
+    }
 
-    write_song_to_file(&song)?;
+    async fn wait_until_ready(&self) {
+        self.session.get().await;
+    }
 
-    Ok(())
+    async fn check(&self) -> Result<()> {
+        self.session.try_get().await.map(|_| ())
+    }
+
+    fn reconfigured(&self) {
+        self.session.outdate();
+    }
+
+    async fn resolve(&self, id: ParsedId) -> Result<Resolution> {
+        match id {
+            ParsedId::DeezerAlbum(id) => self
+                .get_album_tracks(id)
+                .await
+                .ok_or(Error::AlbumNotFound(id.to_string())),
+            ParsedId::DeezerTrack(id) => self
+                .get_song(id)
+                .await
+                .map(|song| vec![song].into())
+                .ok_or(Error::TrackNotFound(id.to_string())),
+            id => Err(Error::UnsupportedId(id)),
+        }
+    }
+
+    async fn download(
+        &self,
+        request: &DownloadRequest,
+        directory: &Path,
+    ) -> Result<DownloadSummary> {
+        let expected_size = estimated_size(request.song.duration, QUALITY.estimated_bitrate());
+        check_free_space(directory, expected_size, &request.config)?;
+
+        let file_name = format!(
+            "{} - {}.{}",
+            request.song.artist,
+            request.song.title,
+            QUALITY.extension()
+        );
+        let file = PartialFile::new(directory.join(replace_illegal_characters(&file_name)));
+
+        let downloader = self.session.get().await;
+        let result = match check_readable(&request.song.id).await {
+            Ok(()) => save_song(&request.song, &downloader, file.path(), &self.throttle).await,
+            Err(err) => Err(err),
+        };
+
+        // Unavailable songs and local errors say nothing about the session,
+        // only failing to reach Deezer or to stream with it does
+        match &result {
+            Ok(_) => self.session.set_status(SourceStatus::Ready),
+            Err(Error::Deezer(_)) => self.session.invalidate().await,
+            Err(_) => {}
+        }
+        result?;
+
+        // A missing cover should not fail the whole download
+        let cover = fetch_cover(&request.song.album.cover_url).await.ok();
+        write_tags(file.path(), &request.song, cover.as_deref())?;
+
+        let processing =
+            post_process(file.path(), &request.config, SourceDownloader::Deezer).await?;
+        let mut warnings = processing.warnings;
+        let path = finish_verified(
+            file,
+            &request.song,
+            processing.trimmed,
+            &request.config,
+            &mut warnings,
+        )
+        .await?;
+
+        Ok(DownloadSummary {
+            quality: Some(QUALITY),
+            trimmed: processing.trimmed,
+            warnings,
+            ..DownloadSummary::new(path)
+        })
+    }
+}
+
+/// Client of the public API, built for each lookup so it always uses the
+/// current proxy and HTTP settings.
+fn deezer_client() -> DeezerClient {
+    DeezerClient::with_client(http::client())
+}
+
+/// Fails with [Error::TrackNotFound] when the track `id` cannot be streamed,
+/// such as when it was removed or is locked in the current region.
+async fn check_readable(id: &str) -> Result<()> {
+    let track_id = id
+        .parse()
+        .map_err(|_| Error::TrackNotFound(id.to_string()))?;
+
+    match deezer_client().track(track_id).await {
+        Ok(Some(track)) if track.readable => Ok(()),
+        Ok(_) => Err(Error::TrackNotFound(id.to_string())),
+        Err(err) => Err(Error::Deezer(err.to_string())),
+    }
 }
 
-/// Write a [Song] to the download directory.
+/// Fetches the audio of `song` with the session into `path`.
 ///
-/// TODO: Allow the target directory to be given.
-fn write_song_to_file(song: &deezer_downloader::Song) -> Result<()> {
-    let Some(download_dir) = download_dir() else {
-        return Ok(());
-    };
-
-    let song_title = format!(
-        "{} - {}.mp3",
-        song.tag.artist().unwrap_or_default(),
-        song.tag.title().unwrap_or_default()
-    );
-    let song_title = replace_illegal_characters(&song_title);
-
-    song.write_to_file(download_dir.join(song_title))
-        .map_err(|_| eyre!("An error occured while writing the file."))?;
+/// The response is read chunk by chunk through `throttle`, so the limits
+/// hold back the transfer itself.
+async fn save_song(
+    song: &Song,
+    downloader: &DeezerDownloader,
+    path: &Path,
+    throttle: &Throttle,
+) -> Result<()> {
+    let mut stream =
+        deezer_downloader::Song::stream_from_metadata(metadata_from_song(song.clone()), downloader)
+            .await
+            .map_err(|err| Error::Deezer(err.to_string()))?;
+    let mut file = File::create(path).await?;
+
+    while let Some(chunk) = stream
+        .chunk()
+        .await
+        .map_err(|err| Error::Deezer(err.to_string()))?
+    {
+        throttle.consume(chunk.len()).await;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
 
     Ok(())
 }
@@ -146,6 +416,6 @@ fn metadata_from_song(song: Song) -> SongMetadata {
             cover_medium: Default::default(),
             cover_small: Default::default(),
         },
-        release_date: Some(song.release_date),
+        release_date: song.release_date.map(|date| date.to_string()),
     }
 }
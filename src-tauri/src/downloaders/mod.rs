@@ -1,20 +1,149 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use crossbeam_channel::Sender;
 use serde::Serialize;
+use ts_rs::TS;
+
+use crate::{
+    albums::{AlbumJobs, JobAlbum, JobTrack},
+    cache::MetadataCache,
+    config::{Config, DeezerQuality, MEGABYTE},
+    ffmpeg::{self, normalize_loudness, trim_silence},
+    http,
+    lyrics::{FallbackProvider, LyricsProvider},
+    models::music::{Resolution, Song, SourceDownloader},
+    parsers::ParsedId,
+    partial::PartialFile,
+    proxy,
+    verification::verify,
+};
 
-use crate::models::music::Song;
+use self::{
+    pool::{Pause, Queue, Slots},
+    throttle::{Limit, Throttle},
+};
 
 pub mod deezer;
+pub mod pool;
+pub mod throttle;
 pub mod youtube;
 
 pub type DeezerId = u64;
 pub type YoutubePlaylistId = String;
 pub type YoutubeId = String;
 
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Duration assumed for songs of unknown length when estimating their size, in seconds.
+static ESTIMATED_DURATION: u32 = 600;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("track {0} not found")]
+    TrackNotFound(String),
+    #[error("album {0} not found")]
+    AlbumNotFound(String),
+    #[error("playlist {0} not found")]
+    PlaylistNotFound(String),
+    #[error("{0:?} cannot be handled by this source")]
+    UnsupportedId(ParsedId),
+    #[error("no downloader registered for {0:?}")]
+    UnknownSource(SourceDownloader),
+    #[error("no download directory available")]
+    NoDownloadDirectory,
+    #[error("not enough free space, {required} MB needed but only {available} MB available")]
+    NotEnoughSpace { required: u64, available: u64 },
+    #[error("{0}")]
+    Deezer(String),
+    #[error(transparent)]
+    Youtube(#[from] rusty_ytdl::VideoError),
+    #[error(transparent)]
+    Ffmpeg(#[from] crate::ffmpeg::Error),
+    #[error("unable to tag the file: {0}")]
+    Tagging(#[from] lofty::error::LoftyError),
+    #[error("invalid download, {0}")]
+    Verification(#[from] crate::verification::Error),
+    #[error(transparent)]
+    Proxy(#[from] crate::proxy::Error),
+    #[error(transparent)]
+    Http(#[from] crate::http::Error),
+    #[error("metadata cache error: {0}")]
+    Cache(#[from] crate::cache::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
 #[derive(Debug, Clone, Serialize, strum_macros::Display)]
 pub enum ProgressEvent {
     Waiting(Song),
     Start(Song),
-    Finish(Song),
+    Finish(Song, DownloadSummary),
     DownloadError(Song, String),
+    SourceStatus(SourceDownloader, SourceStatus),
+    /// Every queue was paused, for the given reason.
+    Paused(String),
+}
+
+/// What was written by a successful download.
+#[derive(TS, Debug, Clone, Serialize)]
+#[ts(export)]
+pub struct DownloadSummary {
+    #[ts(type = "string")]
+    pub path: PathBuf,
+    /// Format of the written file, as its extension.
+    pub format: String,
+    /// Quality the song was downloaded in, for sources that offer a choice.
+    pub quality: Option<DeezerQuality>,
+    /// Silence removed from the file, if trimming was enabled.
+    pub trimmed: Option<TrimmedSilence>,
+    /// Lyrics were found and added to the file.
+    pub lyrics: bool,
+    /// Problems that did not fail the download.
+    pub warnings: Vec<String>,
+}
+
+impl DownloadSummary {
+    pub fn new(path: PathBuf) -> Self {
+        let format = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            format,
+            quality: None,
+            trimmed: None,
+            lyrics: false,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Silence removed from the start and end of a file, in seconds.
+#[derive(TS, Debug, Clone, Copy, Default, Serialize)]
+#[ts(export)]
+pub struct TrimmedSilence {
+    pub leading: f32,
+    pub trailing: f32,
+}
+
+/// Whether a [Source] is currently able to download songs.
+#[derive(TS, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[ts(export)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceStatus {
+    /// Songs can be downloaded.
+    Ready,
+    /// Songs can be downloaded, but recent downloads failed.
+    Degraded,
+    /// Songs cannot be downloaded, they wait until the source is back.
+    Offline,
 }
 
 #[derive(Debug, strum_macros::Display)]
@@ -23,8 +152,417 @@ pub enum DownloadStatus {
     Inactive,
 }
 
+/// A song to download, along with the config at the time it was requested.
+#[derive(Debug, Clone)]
+pub struct DownloadRequest {
+    pub song: Song,
+    pub config: Config,
+    /// Album job the song is part of, downloaded into the folder of the album.
+    pub job: Option<JobTrack>,
+}
+
+/// What a [Source] is able to do.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    /// The source can resolve albums into their tracks.
+    pub albums: bool,
+    /// The source can resolve playlists into their songs.
+    pub playlists: bool,
+    /// The output format can be chosen in the [Config].
+    pub format_choice: bool,
+}
+
+/// A place songs can be looked up and downloaded from.
+#[async_trait]
+pub trait Source: Send + Sync {
+    fn capabilities(&self) -> Capabilities;
+
+    fn status(&self) -> SourceStatus {
+        SourceStatus::Ready
+    }
+
+    /// Waits until the source is able to download songs.
+    async fn wait_until_ready(&self) {}
+
+    /// Checks once whether the source is able to download songs, without
+    /// waiting for it to be ready.
+    async fn check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Resolves an id into every song it refers to.
+    ///
+    /// Every lookup goes through [Sources::resolve], which caches it.
+    async fn resolve(&self, id: ParsedId) -> Result<Resolution>;
+
+    /// Called when a song is added to the download queue of the source.
+    fn requested(&self, _song: &Song) {}
+
+    /// Called when a song that was not downloaded is removed from the queue,
+    /// so the source can let go of what it kept for it.
+    async fn removed(&self, _song: &Song) {}
+
+    /// Called when the proxy or the HTTP settings change, so the source can
+    /// drop the clients it built with the previous ones.
+    fn reconfigured(&self) {}
+
+    /// Downloads a song into `directory`.
+    async fn download(
+        &self,
+        request: &DownloadRequest,
+        directory: &Path,
+    ) -> Result<DownloadSummary>;
+}
+
+/// Every [Source], keyed by [SourceDownloader], each with its own download queue.
+pub struct Sources {
+    sources: HashMap<SourceDownloader, Arc<dyn Source>>,
+    queues: HashMap<SourceDownloader, Queue>,
+    global_slots: Slots,
+    limits: HashMap<SourceDownloader, Limit>,
+    global_limit: Limit,
+    jobs: Arc<AlbumJobs>,
+    pause: Pause,
+    cache: MetadataCache,
+}
+
+impl Sources {
+    pub fn new(config: &Config, cache: MetadataCache, progress_tx: Sender<ProgressEvent>) -> Self {
+        let global_slots = Slots::new(config.max_threads);
+        let lyrics: Arc<dyn LyricsProvider> = Arc::new(FallbackProvider::default());
+        let jobs = Arc::new(AlbumJobs::new());
+        let pause = Pause::new();
+        let global_limit = Limit::default();
+        let limits: HashMap<SourceDownloader, Limit> =
+            [SourceDownloader::Deezer, SourceDownloader::Youtube]
+                .into_iter()
+                .map(|kind| (kind, Limit::default()))
+                .collect();
+        let throttle = |kind| Throttle::new(limits[&kind].clone(), global_limit.clone());
+        let sources: HashMap<SourceDownloader, Arc<dyn Source>> = HashMap::from([
+            (
+                SourceDownloader::Deezer,
+                Arc::new(deezer::Downloader::new(
+                    progress_tx.clone(),
+                    throttle(SourceDownloader::Deezer),
+                )) as Arc<dyn Source>,
+            ),
+            (
+                SourceDownloader::Youtube,
+                Arc::new(youtube::Downloader::new(throttle(
+                    SourceDownloader::Youtube,
+                ))) as Arc<dyn Source>,
+            ),
+        ]);
+
+        let queues = sources
+            .iter()
+            .map(|(&kind, source)| {
+                let queue = Queue::new(
+                    source.clone(),
+                    threads_for(config, kind),
+                    global_slots.clone(),
+                    lyrics.clone(),
+                    jobs.clone(),
+                    pause.clone(),
+                    progress_tx.clone(),
+                );
+
+                (kind, queue)
+            })
+            .collect();
+
+        let sources = Sources {
+            sources,
+            queues,
+            global_slots,
+            limits,
+            global_limit,
+            jobs,
+            pause,
+            cache,
+        };
+        // The default config has no proxy or header to get wrong
+        let _ = sources.apply_config(config);
+
+        sources
+    }
+
+    pub fn get(&self, source: SourceDownloader) -> Result<&Arc<dyn Source>> {
+        self.sources
+            .get(&source)
+            .ok_or(Error::UnknownSource(source))
+    }
+
+    pub fn statuses(&self) -> HashMap<SourceDownloader, SourceStatus> {
+        self.sources
+            .iter()
+            .map(|(&kind, source)| (kind, source.status()))
+            .collect()
+    }
+
+    /// Resolves an id with its source, tagging playlists and splitting videos
+    /// into chapters as the [Config] asks to.
+    ///
+    /// Albums and playlists are given an album job when album folders are
+    /// enabled, which only starts once one of their songs is requested.
+    ///
+    /// What the source resolved is cached, unless some songs failed to
+    /// resolve and could succeed another time.
+    pub async fn resolve(&self, id: ParsedId, config: &Config) -> Result<Resolution> {
+        let settings = &config.metadata_cache;
+        let mut resolution = match self.cache.get(&id, settings).await {
+            Some(resolution) => resolution,
+            None => {
+                let resolution = self.get(id.source())?.resolve(id.clone()).await?;
+
+                if resolution.unresolved.is_empty() {
+                    // A failing cache only makes the next lookup slower
+                    let _ = self.cache.insert(&id, &resolution, settings).await;
+                }
+
+                resolution
+            }
+        };
+
+        if config.split_chapters {
+            resolution.songs = resolution
+                .songs
+                .into_iter()
+                .flat_map(Song::split_chapters)
+                .collect();
+
+            // A single video split into chapters is an album of its own
+            if resolution.album.is_none() && resolution.songs.len() > 1 {
+                resolution.album = resolution.songs.first().map(|song| song.album.clone());
+            }
+        }
+
+        // Splitting first lets chapters know they came from a video
+        resolution.songs = resolution
+            .songs
+            .into_iter()
+            .map(|song| song.tag_playlist(config.playlist_tag))
+            .collect();
+
+        if resolution.album.is_some() && config.album_folders.enabled {
+            resolution.job = Some(self.jobs.reserve());
+        }
+
+        Ok(resolution)
+    }
+
+    /// Queues `request`, starting its album job from `album` if it is the
+    /// first of its songs.
+    pub fn request_download(
+        &self,
+        request: DownloadRequest,
+        album: Option<JobAlbum>,
+    ) -> Result<()> {
+        let source = request.song.source;
+        let queue = self
+            .queues
+            .get(&source)
+            .ok_or(Error::UnknownSource(source))?;
+
+        if let (Some(track), Some(album)) = (request.job, album) {
+            self.jobs.start(track.job, album);
+        }
+
+        self.get(source)?.requested(&request.song);
+        queue.push(request);
+
+        Ok(())
+    }
+
+    /// Removes every cached resolution.
+    pub async fn clear_cache(&self) -> Result<()> {
+        Ok(self.cache.clear().await?)
+    }
+
+    /// Lets the sources know about songs removed from the queue, and completes
+    /// their album jobs without them.
+    pub async fn remove(&self, songs: Vec<Song>, tracks: Vec<JobTrack>, config: &Config) {
+        for song in songs {
+            if let Ok(source) = self.get(song.source) {
+                source.removed(&song).await;
+            }
+        }
+
+        for track in tracks {
+            // The album files are extras, failing to write them fails no song
+            let _ = self.jobs.skip(track, config).await;
+        }
+    }
+
+    /// Removes every song left in the queue, then drops the album jobs that
+    /// have no song downloading anymore.
+    pub async fn clear_queue(&self, songs: Vec<Song>, tracks: Vec<JobTrack>, config: &Config) {
+        self.remove(songs, tracks, config).await;
+        self.jobs.drop_settled();
+    }
+
+    /// Starts the queues again after they were paused.
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Applies the parts of the [Config] that affect running downloaders.
+    ///
+    /// Nothing else is applied if the proxy or the HTTP settings are invalid.
+    pub fn apply_config(&self, config: &Config) -> Result<()> {
+        // Both are checked before either is used, so an invalid setting
+        // changes nothing
+        let proxy = proxy::parse(&config.proxy)?;
+        let client = http::build_client(&config.http, proxy.as_ref())?;
+        proxy::set(proxy);
+        http::set_client(client);
+
+        for source in self.sources.values() {
+            source.reconfigured();
+        }
+
+        for (&kind, queue) in &self.queues {
+            queue.set_threads(threads_for(config, kind));
+        }
+
+        self.global_slots.resize(config.max_threads);
+
+        let schedule = &config.bandwidth.schedule;
+        for (&kind, limit) in &self.limits {
+            limit.set(limit_for(config, kind), schedule.clone());
+        }
+        self.global_limit
+            .set(config.bandwidth.global, schedule.clone());
+
+        ffmpeg::set_directory(config.ffmpeg_directory.as_deref());
+
+        Ok(())
+    }
+
+    /// Checks every source once, see [Source::check].
+    pub async fn check(&self) -> HashMap<SourceDownloader, Result<()>> {
+        let mut checks = HashMap::new();
+
+        for (&kind, source) in &self.sources {
+            checks.insert(kind, source.check().await);
+        }
+
+        checks
+    }
+}
+
+/// What [post_process] did to a file.
+#[derive(Debug, Default)]
+struct Processing {
+    trimmed: Option<TrimmedSilence>,
+    /// Processing that was enabled but could not run.
+    warnings: Vec<String>,
+}
+
+/// Runs the audio processing enabled in the [Config] on a downloaded file.
+///
+/// Deezer files are only processed when asked to, as they are already clean.
+/// Without ffmpeg, the file is kept as is with a warning.
+async fn post_process(
+    path: &Path,
+    config: &Config,
+    source: SourceDownloader,
+) -> Result<Processing> {
+    let is_deezer = source == SourceDownloader::Deezer;
+    let trimming = &config.silence_trimming;
+    let loudness = &config.loudness;
+    let trim = trimming.enabled && (!is_deezer || trimming.apply_to_deezer);
+    let normalize = loudness.enabled && (!is_deezer || loudness.apply_to_deezer);
+
+    if !trim && !normalize {
+        return Ok(Processing::default());
+    }
+
+    if !ffmpeg::is_available().await {
+        return Ok(Processing {
+            warnings: vec!["not processed, ffmpeg was not found".to_string()],
+            ..Default::default()
+        });
+    }
+
+    // Silence is trimmed first so it does not weigh on the loudness measurement
+    let trimmed = if trim {
+        Some(trim_silence(path, trimming).await?)
+    } else {
+        None
+    };
+
+    if normalize {
+        normalize_loudness(path, loudness).await?;
+    }
+
+    Ok(Processing {
+        trimmed,
+        warnings: Vec::new(),
+    })
+}
+
+/// Verifies the file being written, if enabled, then moves it to its final
+/// path.
+///
+/// Invalid files never reach their final path, they are removed along with
+/// `file`. Warnings about the file are added to `warnings`.
+async fn finish_verified(
+    file: PartialFile,
+    song: &Song,
+    trimmed: Option<TrimmedSilence>,
+    config: &Config,
+    warnings: &mut Vec<String>,
+) -> Result<PathBuf> {
+    if config.verification.enabled {
+        warnings.extend(verify(file.path(), song, trimmed, &config.verification).await?);
+    }
+
+    Ok(file.finish().await?)
+}
+
+/// Fails with [Error::NotEnoughSpace] when writing `expected` bytes in
+/// `directory` would leave less free space than the [Config] asks to keep.
+fn check_free_space(directory: &Path, expected: u64, config: &Config) -> Result<()> {
+    if config.min_free_space == 0 {
+        return Ok(());
+    }
+
+    let available = fs2::available_space(directory)?;
+    let required = expected + config.min_free_space as u64 * MEGABYTE;
+
+    if available < required {
+        return Err(Error::NotEnoughSpace {
+            required: required / MEGABYTE,
+            available: available / MEGABYTE,
+        });
+    }
+
+    Ok(())
+}
+
+/// Size of a song lasting `duration` seconds at `bitrate` kbps, in bytes.
+fn estimated_size(duration: Option<u32>, bitrate: u32) -> u64 {
+    duration.unwrap_or(ESTIMATED_DURATION) as u64 * bitrate as u64 * 1000 / 8
+}
+
+fn threads_for(config: &Config, source: SourceDownloader) -> u32 {
+    match source {
+        SourceDownloader::Deezer => config.deezer_threads,
+        SourceDownloader::Youtube => config.youtube_threads,
+    }
+}
+
+fn limit_for(config: &Config, source: SourceDownloader) -> Option<u32> {
+    match source {
+        SourceDownloader::Deezer => config.bandwidth.deezer,
+        SourceDownloader::Youtube => config.bandwidth.youtube,
+    }
+}
+
 /// Replaces illegal characters for a Windows file.
-fn replace_illegal_characters(str: &str) -> String {
+pub fn replace_illegal_characters(str: &str) -> String {
     static ILLEGAL_CHARACTERS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
 
     str.chars()
@@ -36,6 +574,29 @@ fn replace_illegal_characters(str: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn estimates_sizes_from_bitrate() {
+        assert_eq!(estimated_size(Some(200), 320), 8_000_000);
+        assert_eq!(estimated_size(None, 128), 9_600_000);
+    }
+
+    #[test]
+    fn refuses_to_fill_the_disk() {
+        let directory = std::env::temp_dir();
+        let mut config = Config {
+            min_free_space: u32::MAX,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            check_free_space(&directory, 0, &config),
+            Err(Error::NotEnoughSpace { .. })
+        ));
+
+        config.min_free_space = 0;
+        assert!(check_free_space(&directory, u64::MAX, &config).is_ok());
+    }
+
     #[test]
     fn replaces_invalid_chars() {
         let file_name = "AC/DC - Thunderstruck.mp3";
@@ -0,0 +1,445 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crossbeam_channel::Sender;
+use tauri::api::path::download_dir;
+use tokio::sync::{
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    watch, OwnedSemaphorePermit, Semaphore,
+};
+
+use crate::{
+    albums::AlbumJobs,
+    lyrics::{add_lyrics, LyricsProvider},
+};
+
+use super::{DownloadRequest, DownloadSummary, Error, ProgressEvent, Result, Source};
+
+/// A resizable number of download slots.
+///
+/// Cloning [Slots] gives another handle to the same slots, which is how a
+/// single global cap is shared between every source.
+#[derive(Debug, Clone)]
+pub struct Slots {
+    semaphore: Arc<Semaphore>,
+    state: Arc<Mutex<SlotsState>>,
+}
+
+#[derive(Debug)]
+struct SlotsState {
+    size: u32,
+    /// Slots removed while in use, taken back as they are released.
+    owed: u32,
+}
+
+/// A slot in use, given back to its [Slots] when dropped.
+#[derive(Debug)]
+pub struct Slot {
+    permit: Option<OwnedSemaphorePermit>,
+    state: Arc<Mutex<SlotsState>>,
+}
+
+impl Slots {
+    pub fn new(size: u32) -> Self {
+        let size = size.max(1);
+
+        Slots {
+            semaphore: Arc::new(Semaphore::new(size as usize)),
+            state: Arc::new(Mutex::new(SlotsState { size, owed: 0 })),
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.state.lock().unwrap().size
+    }
+
+    /// Changes the number of slots.
+    ///
+    /// Growing takes effect immediately. Shrinking takes back the free slots,
+    /// and the ones in use once released, so nothing in progress is
+    /// interrupted.
+    pub fn resize(&self, size: u32) {
+        let size = size.max(1);
+        let mut state = self.state.lock().unwrap();
+
+        if size > state.size {
+            // Slots still owed are kept instead of being taken back later
+            let added = size - state.size;
+            let paid = added.min(state.owed);
+
+            state.owed -= paid;
+            self.semaphore.add_permits((added - paid) as usize);
+        } else if size < state.size {
+            let mut removed = state.size - size;
+
+            while removed > 0 {
+                let Ok(permit) = self.semaphore.try_acquire() else {
+                    break;
+                };
+                permit.forget();
+                removed -= 1;
+            }
+            state.owed += removed;
+        }
+
+        state.size = size;
+    }
+
+    pub async fn acquire(&self) -> Slot {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Semaphore should never be closed");
+
+        Slot {
+            permit: Some(permit),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        let Some(permit) = self.permit.take() else {
+            return;
+        };
+
+        if state.owed > 0 {
+            state.owed -= 1;
+            permit.forget();
+        }
+    }
+}
+
+/// Holds back every queue at once, until it is resumed.
+///
+/// Like [Slots], cloning [Pause] gives another handle to the same pause.
+#[derive(Debug, Clone)]
+pub struct Pause {
+    paused: Arc<watch::Sender<bool>>,
+}
+
+impl Pause {
+    pub fn new() -> Self {
+        Pause {
+            paused: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Pauses the queues, returning whether they were running until now.
+    pub fn pause(&self) -> bool {
+        !self.paused.send_replace(true)
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Waits until the queues are not paused.
+    pub async fn wait_until_resumed(&self) {
+        let mut paused = self.paused.subscribe();
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+}
+
+impl Default for Pause {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Takes requests from `download_rx` one at a time and hands each of them to
+/// `work` once a source slot is free, `ready` resolved and a global slot is
+/// free.
+///
+/// Requests that cannot be started yet stay in the channel, so resizing the
+/// slots never drops anything from the queue. Waiting for `ready` before
+/// taking a global slot leaves the global slots to the sources able to use
+/// them.
+pub fn spawn_dispatcher<T, R, ReadyFut, F, Fut>(
+    mut download_rx: UnboundedReceiver<T>,
+    slots: Slots,
+    global_slots: Slots,
+    ready: R,
+    work: F,
+) where
+    T: Send + 'static,
+    R: Fn() -> ReadyFut + Send + Sync + 'static,
+    ReadyFut: Future<Output = ()> + Send,
+    F: Fn(T) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        while let Some(request) = download_rx.recv().await {
+            let slot = slots.acquire().await;
+            ready().await;
+            let global_slot = global_slots.acquire().await;
+            let job = work(request);
+
+            tokio::spawn(async move {
+                job.await;
+
+                drop(global_slot);
+                drop(slot);
+            });
+        }
+    });
+}
+
+/// Downloads `request` with `source`, which verifies the written file before
+/// moving it to its final path, see [finish_verified].
+///
+/// Incomplete files are downloaded again up to the configured number of
+/// retries, the others would come out the same.
+///
+/// [finish_verified]: super::finish_verified
+async fn download_verified(
+    source: &dyn Source,
+    request: &DownloadRequest,
+    directory: &Path,
+) -> Result<DownloadSummary> {
+    let settings = &request.config.verification;
+    let mut retries = 0;
+
+    loop {
+        match source.download(request, directory).await {
+            Err(Error::Verification(err)) if err.is_retryable() && retries < settings.retries => {
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Adds lyrics to a downloaded file, if enabled.
+///
+/// Songs without lyrics are common, so failing to add them does not fail the
+/// download.
+async fn with_lyrics(
+    summary: DownloadSummary,
+    request: &DownloadRequest,
+    provider: &dyn LyricsProvider,
+) -> DownloadSummary {
+    let settings = &request.config.lyrics;
+    if !settings.enabled {
+        return summary;
+    }
+
+    let trimmed_lead = summary
+        .trimmed
+        .map_or(0, |trimmed| (trimmed.leading * 1000.0) as u32);
+    let lyrics = add_lyrics(
+        &summary.path,
+        &request.song,
+        trimmed_lead,
+        settings,
+        provider,
+    )
+    .await
+    .unwrap_or(false);
+
+    DownloadSummary { lyrics, ..summary }
+}
+
+/// Downloads `request` into its directory, then adds its lyrics.
+async fn download(
+    source: &dyn Source,
+    request: &DownloadRequest,
+    jobs: &AlbumJobs,
+    lyrics: &dyn LyricsProvider,
+) -> Result<DownloadSummary> {
+    let directory = download_directory(request, jobs).await?;
+    let summary = download_verified(source, request, &directory).await?;
+
+    Ok(with_lyrics(summary, request, lyrics).await)
+}
+
+/// Directory `request` is downloaded into, the folder of its album if it is
+/// part of an album job.
+async fn download_directory(request: &DownloadRequest, jobs: &AlbumJobs) -> Result<PathBuf> {
+    let directory = download_dir().ok_or(Error::NoDownloadDirectory)?;
+    let Some(folder) = request.job.and_then(|track| jobs.folder(track.job)) else {
+        return Ok(directory);
+    };
+
+    let directory = directory.join(folder);
+    tokio::fs::create_dir_all(&directory).await?;
+
+    Ok(directory)
+}
+
+/// The download queue of a single [Source].
+pub struct Queue {
+    download_tx: UnboundedSender<DownloadRequest>,
+    slots: Slots,
+}
+
+impl Queue {
+    pub fn new(
+        source: Arc<dyn Source>,
+        threads: u32,
+        global_slots: Slots,
+        lyrics: Arc<dyn LyricsProvider>,
+        jobs: Arc<AlbumJobs>,
+        pause: Pause,
+        progress_tx: Sender<ProgressEvent>,
+    ) -> Self {
+        let (download_tx, download_rx) = unbounded_channel::<DownloadRequest>();
+        let slots = Slots::new(threads);
+
+        let ready = {
+            let source = source.clone();
+            let pause = pause.clone();
+
+            move || {
+                let source = source.clone();
+                let pause = pause.clone();
+
+                async move {
+                    pause.wait_until_resumed().await;
+                    source.wait_until_ready().await;
+                }
+            }
+        };
+
+        spawn_dispatcher(
+            download_rx,
+            slots.clone(),
+            global_slots,
+            ready,
+            move |request| {
+                let source = source.clone();
+                let lyrics = lyrics.clone();
+                let jobs = jobs.clone();
+                let pause = pause.clone();
+                let progress_tx = progress_tx.clone();
+
+                async move {
+                    let song = request.song.clone();
+                    progress_tx
+                        .send(ProgressEvent::Start(song.clone()))
+                        .unwrap();
+
+                    let result = loop {
+                        let result =
+                            download(source.as_ref(), &request, &jobs, lyrics.as_ref()).await;
+                        let Err(err @ Error::NotEnoughSpace { .. }) = &result else {
+                            break result;
+                        };
+
+                        // The next songs would run out of space too, every queue
+                        // waits for space to be freed, then the song is tried again
+                        if pause.pause() {
+                            progress_tx
+                                .send(ProgressEvent::Paused(err.to_string()))
+                                .unwrap();
+                        }
+                        pause.wait_until_resumed().await;
+                        source.wait_until_ready().await;
+                    };
+                    let path = result.as_ref().ok().map(|summary| summary.path.clone());
+                    let progress = match result {
+                        Ok(summary) => ProgressEvent::Finish(song, summary),
+                        Err(err) => ProgressEvent::DownloadError(song, err.to_string()),
+                    };
+                    progress_tx.send(progress).unwrap();
+
+                    // The album files are extras, failing to write them fails no song
+                    if let Some(track) = request.job {
+                        let _ = jobs.complete(track, path, &request.config).await;
+                    }
+                }
+            },
+        );
+
+        Queue { download_tx, slots }
+    }
+
+    pub fn push(&self, request: DownloadRequest) {
+        self.download_tx
+            .send(request)
+            .expect("Channel should be open");
+    }
+
+    /// Changes how many songs are downloaded at the same time.
+    pub fn set_threads(&self, threads: u32) {
+        self.slots.resize(threads);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn resizes_slots() {
+        let slots = Slots::new(2);
+        assert_eq!(slots.size(), 2);
+
+        slots.resize(4);
+        assert_eq!(slots.semaphore.available_permits(), 4);
+
+        let first = slots.acquire().await;
+        let _second = slots.acquire().await;
+        slots.resize(1);
+        assert_eq!(slots.size(), 1);
+        // The free slots are taken back right away, one is still owed
+        assert_eq!(slots.semaphore.available_permits(), 0);
+
+        // Growing keeps the owed slot instead of adding one
+        slots.resize(2);
+        assert_eq!(slots.semaphore.available_permits(), 0);
+
+        drop(first);
+        assert_eq!(slots.semaphore.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn takes_back_slots_as_they_are_released() {
+        let slots = Slots::new(2);
+        let first = slots.acquire().await;
+        let second = slots.acquire().await;
+
+        slots.resize(1);
+        drop(first);
+        assert_eq!(slots.semaphore.available_permits(), 0);
+
+        drop(second);
+        assert_eq!(slots.semaphore.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn waits_until_resumed() {
+        let pause = Pause::new();
+        assert!(pause.pause());
+        assert!(!pause.pause());
+
+        let waiting = tokio::spawn({
+            let pause = pause.clone();
+            async move { pause.wait_until_resumed().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        pause.resume();
+        waiting.await.unwrap();
+        assert!(!pause.is_paused());
+    }
+
+    #[test]
+    fn keeps_at_least_one_slot() {
+        let slots = Slots::new(0);
+
+        assert_eq!(slots.size(), 1);
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{Local, Timelike};
+
+use crate::config::LimitSchedule;
+
+/// Bytes a limit lets through at once after being idle, in seconds of its rate.
+static BURST_SECONDS: f64 = 1.0;
+/// Smallest chunk streams are fetched in when limited, in bytes, so slow
+/// rates do not turn into a request every few bytes.
+static MIN_CHUNK_SIZE: u64 = 16 * 1024;
+
+/// A download rate shared by every download it applies to.
+///
+/// Like [super::pool::Slots], cloning [Limit] gives another handle to the
+/// same rate.
+#[derive(Debug, Clone, Default)]
+pub struct Limit {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Rate in bytes per second, unlimited when unset.
+    rate: Option<u32>,
+    schedule: Option<LimitSchedule>,
+    /// Bytes that can be downloaded right away, negative when downloads got
+    /// ahead of the rate.
+    allowance: f64,
+    updated: Instant,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Bucket {
+            rate: None,
+            schedule: None,
+            allowance: 0.0,
+            updated: Instant::now(),
+        }
+    }
+}
+
+impl Bucket {
+    /// Rate in effect at `minute`, from midnight.
+    fn active_rate(&self, minute: u32) -> Option<u32> {
+        let scheduled = self
+            .schedule
+            .iter()
+            .all(|schedule| in_schedule(schedule, minute));
+
+        self.rate.filter(|_| scheduled)
+    }
+
+    /// Takes `bytes` from the allowance, returning how long to wait for them.
+    fn take(&mut self, bytes: usize, now: Instant, minute: u32) -> Duration {
+        let Some(rate) = self.active_rate(minute).map(f64::from) else {
+            return Duration::ZERO;
+        };
+
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.allowance = (self.allowance + elapsed * rate).min(rate * BURST_SECONDS);
+        self.updated = now;
+        self.allowance -= bytes as f64;
+
+        if self.allowance < 0.0 {
+            Duration::from_secs_f64(-self.allowance / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+impl Limit {
+    /// Changes the rate, in bytes per second, and the hours it applies in.
+    pub fn set(&self, rate: Option<u32>, schedule: Option<LimitSchedule>) {
+        let mut bucket = self.bucket.lock().unwrap();
+
+        bucket.rate = rate.filter(|&rate| rate > 0);
+        bucket.schedule = schedule;
+    }
+
+    fn take(&self, bytes: usize, now: Instant, minute: u32) -> Duration {
+        self.bucket.lock().unwrap().take(bytes, now, minute)
+    }
+
+    fn active_rate(&self, minute: u32) -> Option<u32> {
+        self.bucket.lock().unwrap().active_rate(minute)
+    }
+}
+
+/// The limits the downloads of a source are under: its own and the global one.
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    source: Limit,
+    global: Limit,
+}
+
+impl Throttle {
+    pub fn new(source: Limit, global: Limit) -> Self {
+        Throttle { source, global }
+    }
+
+    /// Waits until `bytes` more can be downloaded without going over the limits.
+    pub async fn consume(&self, bytes: usize) {
+        let now = Instant::now();
+        let minute = minute_of_day();
+
+        let delay = self
+            .source
+            .take(bytes, now, minute)
+            .max(self.global.take(bytes, now, minute));
+
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Size of the chunks to fetch streams in, about a second of the lowest
+    /// rate in effect, `None` when nothing is limited.
+    ///
+    /// Bytes are only counted once fetched, so smaller chunks spread them
+    /// evenly instead of in bursts.
+    pub fn chunk_size(&self) -> Option<u64> {
+        let minute = minute_of_day();
+
+        [&self.source, &self.global]
+            .into_iter()
+            .filter_map(|limit| limit.active_rate(minute))
+            .min()
+            .map(|rate| u64::from(rate).max(MIN_CHUNK_SIZE))
+    }
+}
+
+/// Minutes from midnight, in local time.
+fn minute_of_day() -> u32 {
+    let time = Local::now().time();
+
+    time.hour() * 60 + time.minute()
+}
+
+/// Whether `minute`, from midnight, is within the hours of `schedule`.
+///
+/// Schedules ending before they start wrap around midnight, and unreadable
+/// ones always apply.
+fn in_schedule(schedule: &LimitSchedule, minute: u32) -> bool {
+    let (Some(start), Some(end)) = (parse_time(&schedule.start), parse_time(&schedule.end)) else {
+        return true;
+    };
+
+    if start <= end {
+        (start..end).contains(&minute)
+    } else {
+        minute >= start || minute < end
+    }
+}
+
+/// Parses times such as `09:30` into minutes from midnight.
+fn parse_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(start: &str, end: &str) -> LimitSchedule {
+        LimitSchedule {
+            start: start.to_string(),
+            end: end.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("09:30"), Some(570));
+        assert_eq!(parse_time("0:00"), Some(0));
+        assert_eq!(parse_time("24:00"), None);
+        assert_eq!(parse_time("noon"), None);
+    }
+
+    #[test]
+    fn wraps_schedules_around_midnight() {
+        let office = schedule("09:00", "18:00");
+        assert!(in_schedule(&office, 9 * 60));
+        assert!(!in_schedule(&office, 18 * 60));
+
+        let night = schedule("22:00", "06:00");
+        assert!(in_schedule(&night, 23 * 60));
+        assert!(in_schedule(&night, 60));
+        assert!(!in_schedule(&night, 12 * 60));
+    }
+
+    #[test]
+    fn delays_bytes_over_the_rate() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            rate: Some(1000),
+            allowance: 1000.0,
+            updated: start,
+            ..Default::default()
+        };
+
+        assert_eq!(bucket.take(1000, start, 0), Duration::ZERO);
+        assert_eq!(bucket.take(500, start, 0), Duration::from_millis(500));
+
+        // The debt is paid back over time
+        let later = start + Duration::from_secs(1);
+        assert_eq!(bucket.take(0, later, 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn lets_everything_through_outside_the_schedule() {
+        let mut bucket = Bucket {
+            rate: Some(1),
+            schedule: Some(schedule("09:00", "18:00")),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            bucket.take(1_000_000, Instant::now(), 20 * 60),
+            Duration::ZERO
+        );
+        assert!(!bucket.take(1_000_000, Instant::now(), 10 * 60).is_zero());
+    }
+
+    #[test]
+    fn fetches_in_chunks_of_the_lowest_rate() {
+        let throttle = Throttle::default();
+        assert_eq!(throttle.chunk_size(), None);
+
+        throttle.source.set(Some(500_000), None);
+        throttle.global.set(Some(200_000), None);
+        assert_eq!(throttle.chunk_size(), Some(200_000));
+
+        throttle.global.set(Some(10), None);
+        assert_eq!(throttle.chunk_size(), Some(MIN_CHUNK_SIZE));
+    }
+}
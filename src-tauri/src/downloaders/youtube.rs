@@ -1,101 +1,478 @@
-use crossbeam_channel::{unbounded, Sender};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
 use rusty_ytdl::{
+    choose_format,
     search::{Playlist, PlaylistSearchOptions},
-    FFmpegArgs, Video, VideoError,
+    stream::Stream,
+    DownloadOptions, RequestOptions, Video, VideoFormat, VideoOptions, VideoQuality,
+    VideoSearchOptions,
 };
-use tauri::api::path::download_dir;
+use tokio::{fs::File, io::AsyncWriteExt, sync::OnceCell};
 
-use crate::{config::YoutubeFormat, models::music::Song};
-
-use super::{replace_illegal_characters, ProgressEvent, YoutubeId, YoutubePlaylistId};
+use crate::{
+    artwork::fetch_square_cover,
+    config::{Config, YoutubeFormat},
+    ffmpeg::{self, Encoder},
+    http,
+    models::music::{Album, PlaylistEntry, Resolution, Segment, Song, SourceDownloader},
+    parsers::ParsedId,
+    partial::{partial_path, PartialFile},
+    proxy,
+    tagging::{ffmpeg_metadata, write_tags},
+};
 
-static DOWNLOAD_THREADS: u64 = 4;
+use super::{
+    check_free_space, estimated_size, finish_verified, post_process, replace_illegal_characters,
+    throttle::Throttle, Capabilities, DownloadRequest, DownloadSummary, Error, Result, Source,
+    YoutubeId, YoutubePlaylistId,
+};
 
-pub struct YoutubeRequest {
-    pub song: Song,
-    pub format: YoutubeFormat,
+/// Original stream of a video split into several songs, fetched once for all
+/// of them.
+#[derive(Debug, Default)]
+struct SharedStream {
+    path: Arc<OnceCell<PathBuf>>,
+    /// Indexes of the songs of the video queued or being downloaded, which
+    /// still need the stream.
+    outstanding: HashSet<u32>,
 }
 
+#[derive(Debug, Default)]
 pub struct Downloader {
-    download_tx: Sender<YoutubeRequest>,
+    shared_streams: Mutex<HashMap<YoutubeId, SharedStream>>,
+    throttle: Throttle,
 }
 
 impl Downloader {
-    pub fn new(progress_tx: Sender<ProgressEvent>) -> Self {
-        let (download_tx, download_rx) = unbounded::<YoutubeRequest>();
-
-        for _ in 0..DOWNLOAD_THREADS {
-            let _download_rx = download_rx.clone();
-            let _progress_tx = progress_tx.clone();
-
-            tokio::spawn(async move {
-                while let Ok(YoutubeRequest { song, format }) = _download_rx.recv() {
-                    _progress_tx
-                        .send(ProgressEvent::Start(song.clone()))
-                        .unwrap();
-
-                    let result = download_song(&song, &format).await;
-                    let progress = match result {
-                        Ok(_) => ProgressEvent::Finish(song),
-                        Err(err) => ProgressEvent::DownloadError(song, err.to_string()),
-                    };
-
-                    _progress_tx.send(progress).unwrap();
-                }
-            });
+    pub fn new(throttle: Throttle) -> Self {
+        Downloader {
+            throttle,
+            ..Default::default()
         }
-
-        Downloader { download_tx }
-    }
-
-    pub async fn request_download(&self, request: YoutubeRequest) -> Result<(), VideoError> {
-        self.download_tx
-            .send(request)
-            .expect("Channel should be open");
-
-        Ok(())
     }
 
     pub async fn get_song(&self, id: YoutubeId) -> Option<Song> {
-        let video = Video::new(id.to_string()).ok()?;
+        let options = VideoOptions {
+            request_options: request_options(),
+            ..Default::default()
+        };
+        let video = Video::new_with_options(id.to_string(), options).ok()?;
         let video_details = video.get_basic_info().await.ok()?.video_details;
 
         Some(video_details.into())
     }
 
-    pub async fn get_playlist_songs(&self, id: YoutubePlaylistId) -> Option<Vec<Song>> {
+    /// Resolves every video of a playlist, the playlist becoming their album.
+    pub async fn get_playlist_songs(&self, id: YoutubePlaylistId) -> Option<Resolution> {
         let options = PlaylistSearchOptions {
             fetch_all: true,
+            request_options: Some(request_options()),
             ..Default::default()
         };
         let playlist = Playlist::get(id.to_string(), Some(&options)).await.ok()?;
-        let songs = playlist
-            .videos
+        let total = playlist.videos.len() as u32;
+        let songs: Vec<Song> = playlist.videos.into_iter().map(Song::from).collect();
+
+        // Playlists have no cover of their own, the first video stands for it
+        let cover_url = songs
+            .first()
+            .map(|song| song.album.cover_url.clone())
+            .unwrap_or_default();
+        let songs = songs
             .into_iter()
-            .map(|video| video.into())
+            .enumerate()
+            .map(|(index, song)| {
+                let position = index as u32 + 1;
+
+                Song {
+                    track_number: Some(position),
+                    playlist: Some(PlaylistEntry {
+                        title: playlist.name.clone(),
+                        owner: Some(playlist.channel.name.clone()),
+                        cover_url: cover_url.clone(),
+                        position,
+                        total,
+                    }),
+                    ..song
+                }
+            })
             .collect();
 
-        Some(songs)
+        let album = Album {
+            title: playlist.name,
+            cover_url,
+            artist: Some(playlist.channel.name),
+            total_tracks: Some(total),
+            ..Default::default()
+        };
+
+        Some(Resolution {
+            album: Some(album),
+            ..songs.into()
+        })
+    }
+
+    /// Downloads a song cut from a video split into several songs.
+    async fn download_segment(
+        &self,
+        song: &Song,
+        segment: &Segment,
+        config: &Config,
+        directory: &Path,
+    ) -> Result<DownloadSummary> {
+        let bitrate = config
+            .youtube_format
+            .estimated_bitrate(&config.youtube_encoding);
+        check_free_space(directory, estimated_size(song.duration, bitrate), config)?;
+
+        let result = match self.shared_stream(song, segment, directory).await {
+            Ok(source) => cut_segment(&source, song, segment, config, directory).await,
+            Err(err) => Err(err),
+        };
+        self.release_shared_stream(&song.id, segment.index).await;
+
+        finish_download(result?, song, config).await
+    }
+
+    /// Returns the original stream of the video `song` is cut from, fetching it
+    /// only once for every song of the video.
+    async fn shared_stream(
+        &self,
+        song: &Song,
+        segment: &Segment,
+        directory: &Path,
+    ) -> Result<PathBuf> {
+        let path = {
+            let mut streams = self.shared_streams.lock().unwrap();
+            let stream = streams.entry(song.id.clone()).or_default();
+
+            // A song removed from the queue may still be downloaded
+            stream.outstanding.insert(segment.index);
+            stream.path.clone()
+        };
+
+        path.get_or_try_init(|| fetch_original(&song.id, directory, &self.throttle))
+            .await
+            .cloned()
+    }
+
+    /// Lets go of the shared stream of a video for the song at `index`,
+    /// removing the stream once no other song of the video needs it.
+    async fn release_shared_stream(&self, id: &str, index: u32) {
+        let path = {
+            let mut streams = self.shared_streams.lock().unwrap();
+            let Some(stream) = streams.get_mut(id) else {
+                return;
+            };
+
+            stream.outstanding.remove(&index);
+            if !stream.outstanding.is_empty() {
+                return;
+            }
+
+            streams
+                .remove(id)
+                .and_then(|stream| stream.path.get().cloned())
+        };
+
+        if let Some(path) = path {
+            let _ = tokio::fs::remove_file(path).await;
+        }
     }
 }
 
-async fn download_song(song: &Song, format: &YoutubeFormat) -> Result<(), VideoError> {
-    let file_format: String = format.to_string();
-    let video = Video::new(song.id.clone())?;
+#[async_trait]
+impl Source for Downloader {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            albums: false,
+            playlists: true,
+            format_choice: true,
+        }
+    }
+
+    async fn resolve(&self, id: ParsedId) -> Result<Resolution> {
+        match id {
+            ParsedId::YoutubeVideo(id) => self
+                .get_song(id.clone())
+                .await
+                .map(|song| vec![song].into())
+                .ok_or(Error::TrackNotFound(id)),
+            ParsedId::YoutubePlaylist(id) => self
+                .get_playlist_songs(id.clone())
+                .await
+                .ok_or(Error::PlaylistNotFound(id)),
+            id => Err(Error::UnsupportedId(id)),
+        }
+    }
+
+    /// Keeps the shared stream of a video until every requested song of the
+    /// video is downloaded.
+    fn requested(&self, song: &Song) {
+        if let Some(segment) = &song.segment {
+            self.shared_streams
+                .lock()
+                .unwrap()
+                .entry(song.id.clone())
+                .or_default()
+                .outstanding
+                .insert(segment.index);
+        }
+    }
+
+    async fn removed(&self, song: &Song) {
+        if let Some(segment) = &song.segment {
+            self.release_shared_stream(&song.id, segment.index).await;
+        }
+    }
+
+    async fn download(
+        &self,
+        request: &DownloadRequest,
+        directory: &Path,
+    ) -> Result<DownloadSummary> {
+        let DownloadRequest { song, config, .. } = request;
+
+        match &song.segment {
+            Some(segment) => {
+                self.download_segment(song, segment, config, directory)
+                    .await
+            }
+            None => download_song(song, config, directory, &self.throttle).await,
+        }
+    }
+}
+
+/// Options of the audio stream of a video, fetched in chunks small enough for
+/// `throttle` to smooth the rate out.
+fn audio_options(throttle: &Throttle) -> VideoOptions {
+    VideoOptions {
+        quality: VideoQuality::HighestAudio,
+        filter: VideoSearchOptions::Audio,
+        download_options: DownloadOptions {
+            dl_chunk_size: throttle.chunk_size(),
+        },
+        request_options: request_options(),
+    }
+}
+
+/// Options of every request to Youtube, made with the shared client.
+///
+/// The proxy is only used if the library builds a client of its own.
+fn request_options() -> RequestOptions {
+    RequestOptions {
+        client: Some(http::client()),
+        proxy: proxy::reqwest_proxy(),
+        ..Default::default()
+    }
+}
 
-    // TODO: Allow the target directory to be given.
-    let title = format!(
+async fn download_song(
+    song: &Song,
+    config: &Config,
+    directory: &Path,
+    throttle: &Throttle,
+) -> Result<DownloadSummary> {
+    let options = audio_options(throttle);
+    let video = Video::new_with_options(song.id.clone(), options.clone())?;
+
+    let original = original_format(&video, &options).await?;
+    let stream_extension = format_extension(&original);
+
+    // Without ffmpeg, the best audio stream is saved as is
+    let transcode_format = match &config.youtube_format {
+        format if is_original(format, &stream_extension, config) => None,
+        _ if !ffmpeg::is_available().await => None,
+        format => Some(format),
+    };
+
+    let (extension, expected_size) = match transcode_format {
+        Some(format) => {
+            let bitrate = format.estimated_bitrate(&config.youtube_encoding);
+            (format.to_string(), estimated_size(song.duration, bitrate))
+        }
+        None => {
+            let bitrate = YoutubeFormat::ORIGINAL.estimated_bitrate(&config.youtube_encoding);
+            let size = original
+                .content_length
+                .as_deref()
+                .and_then(|length| length.parse().ok())
+                .unwrap_or_else(|| estimated_size(song.duration, bitrate));
+
+            (stream_extension, size)
+        }
+    };
+    check_free_space(directory, expected_size, config)?;
+
+    let title = format!("{}.{}", replace_illegal_characters(&song.title), extension);
+    let file = PartialFile::new(directory.join(title));
+
+    match transcode_format {
+        Some(format) => {
+            let stream = video.stream().await?;
+            let mut encoder = Encoder::spawn(file.path(), format, &config.youtube_encoding).await?;
+            let written = write_stream(stream.as_ref(), &mut encoder, throttle).await;
+
+            // A failing encoder explains a failed write better than the write itself
+            encoder.finish().await?;
+            written?;
+        }
+        None => save_stream(&video, file.path(), throttle).await?,
+    }
+
+    finish_download(file, song, config).await
+}
+
+/// Saves the original audio stream of the video `id` in `directory`, under a
+/// hidden name.
+async fn fetch_original(id: &str, directory: &Path, throttle: &Throttle) -> Result<PathBuf> {
+    let options = audio_options(throttle);
+    let video = Video::new_with_options(id, options.clone())?;
+    let extension = original_extension(&video, &options).await?;
+    let path = partial_path(&directory.join(format!("{id}.source.{extension}")));
+
+    if let Err(err) = save_stream(&video, &path, throttle).await {
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(err);
+    }
+
+    Ok(path)
+}
+
+/// Cuts `segment` out of the original stream of its video.
+async fn cut_segment(
+    source: &Path,
+    song: &Song,
+    segment: &Segment,
+    config: &Config,
+    directory: &Path,
+) -> Result<PartialFile> {
+    let source_extension = source
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let format = match &config.youtube_format {
+        format if is_original(format, &source_extension, config) => None,
+        format => Some(format),
+    };
+    let extension = match format {
+        Some(format) => format.to_string(),
+        None => source_extension,
+    };
+    let file = PartialFile::new(directory.join(format!(
         "{}.{}",
         replace_illegal_characters(&song.title),
-        file_format
-    );
-    let video_path = download_dir().unwrap().join(title);
-    let args = FFmpegArgs {
-        format: Some(file_format),
-        audio_filter: None,
-        video_filter: None,
-    };
-    video.download_with_ffmpeg(video_path, Some(args)).await?;
+        extension
+    )));
+
+    ffmpeg::cut(
+        source,
+        file.path(),
+        (segment.start, segment.end),
+        format,
+        &config.youtube_encoding,
+    )
+    .await?;
+
+    Ok(file)
+}
+
+/// Processes, tags and adds the cover to a downloaded file, then moves it to
+/// its final path.
+async fn finish_download(
+    file: PartialFile,
+    song: &Song,
+    config: &Config,
+) -> Result<DownloadSummary> {
+    let processing = post_process(file.path(), config, SourceDownloader::Youtube).await?;
+
+    // A missing cover should not fail the whole download
+    let cover = fetch_square_cover(&song.album.cover_url, config.cover_size)
+        .await
+        .ok();
+
+    let mut warnings = processing.warnings;
+
+    // lofty cannot write WebM, ffmpeg tags it without the cover
+    if !write_tags(file.path(), song, cover.as_deref())? {
+        if ffmpeg::is_available().await {
+            ffmpeg::write_metadata(file.path(), &ffmpeg_metadata(song)).await?;
+        } else {
+            warnings.push("not tagged, ffmpeg was not found".to_string());
+        }
+    }
+    let path = finish_verified(file, song, processing.trimmed, config, &mut warnings).await?;
+
+    // Named after the song, so songs downloaded together keep their own
+    if let (Some(cover), true) = (&cover, config.save_cover_file) {
+        tokio::fs::write(path.with_extension("jpg"), cover).await?;
+    }
+
+    Ok(DownloadSummary {
+        trimmed: processing.trimmed,
+        warnings,
+        ..DownloadSummary::new(path)
+    })
+}
+
+async fn save_stream(video: &Video, path: &Path, throttle: &Throttle) -> Result<()> {
+    let stream = video.stream().await?;
+    let mut file = File::create(path).await?;
+
+    while let Some(chunk) = stream.chunk().await? {
+        throttle.consume(chunk.len()).await;
+        file.write_all(&chunk).await?;
+    }
+
+    Ok(())
+}
+
+async fn write_stream(
+    stream: &(dyn Stream + Send + Sync),
+    encoder: &mut Encoder,
+    throttle: &Throttle,
+) -> Result<()> {
+    while let Some(chunk) = stream.chunk().await? {
+        throttle.consume(chunk.len()).await;
+        encoder.write(&chunk).await?;
+    }
 
     Ok(())
 }
+
+/// Audio stream of `video` saved without transcoding.
+async fn original_format(video: &Video, options: &VideoOptions) -> Result<VideoFormat> {
+    let formats = video.get_info().await?.formats;
+
+    Ok(choose_format(&formats, options)?.clone())
+}
+
+/// Extension of the audio stream of `video` saved without transcoding.
+async fn original_extension(video: &Video, options: &VideoOptions) -> Result<String> {
+    Ok(format_extension(&original_format(video, options).await?))
+}
+
+/// Whether `format` keeps the original stream, saved as `extension`, as is.
+///
+/// Streams already in the chosen format are only transcoded to apply
+/// encoder settings.
+fn is_original(format: &YoutubeFormat, extension: &str, config: &Config) -> bool {
+    match format {
+        YoutubeFormat::ORIGINAL => true,
+        format => format.to_string() == extension && config.youtube_encoding.is_unset(),
+    }
+}
+
+fn format_extension(format: &VideoFormat) -> String {
+    let extension = match &format.mime_type.container[..] {
+        "mp4" => "m4a",
+        container => container,
+    };
+
+    extension.to_string()
+}
@@ -1,7 +1,10 @@
 use serde::Serialize;
 use ts_rs::TS;
 
-use crate::{downloaders::ProgressEvent, models::music::Song};
+use crate::{
+    downloaders::{DownloadSummary, ProgressEvent, SourceStatus},
+    models::music::{Song, SourceDownloader},
+};
 
 #[derive(Clone, TS, Serialize, strum_macros::Display)]
 #[ts(export)]
@@ -10,9 +13,12 @@ use crate::{downloaders::ProgressEvent, models::music::Song};
 pub enum Event {
     Waiting(Song),
     Start(Song),
-    Finish(Song),
+    Finish(Song, DownloadSummary),
     DownloadError(Song, String),
     RemoveFromQueue(Song),
+    SourceStatus(SourceDownloader, SourceStatus),
+    /// Every queue was paused, for the given reason.
+    Paused(String),
 }
 
 impl From<ProgressEvent> for Event {
@@ -20,8 +26,10 @@ impl From<ProgressEvent> for Event {
         match event {
             ProgressEvent::Waiting(song) => Self::Waiting(song),
             ProgressEvent::Start(song) => Self::Start(song),
-            ProgressEvent::Finish(song) => Self::Finish(song),
+            ProgressEvent::Finish(song, summary) => Self::Finish(song, summary),
             ProgressEvent::DownloadError(song, err_msg) => Self::DownloadError(song, err_msg),
+            ProgressEvent::SourceStatus(source, status) => Self::SourceStatus(source, status),
+            ProgressEvent::Paused(reason) => Self::Paused(reason),
         }
     }
 }
@@ -0,0 +1,858 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+    sync::{Arc, RwLock},
+};
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use tokio::{
+    io::AsyncWriteExt,
+    process::{Child, ChildStdin, Command},
+    sync::OnceCell,
+};
+
+use crate::{
+    config::{AudioEncoding, LoudnessNormalization, SilenceTrimming, YoutubeFormat},
+    downloaders::TrimmedSilence,
+};
+
+/// Loudness range targeted by the normalisation, in LU.
+static TARGET_LRA: f32 = 11.0;
+/// Distance from the start or end of the file under which a silence is
+/// considered to touch it, in seconds.
+static SILENCE_EDGE_MARGIN: f64 = 0.05;
+
+/// Directory ffmpeg and ffprobe are taken from, instead of the `PATH`.
+static DIRECTORY: RwLock<Option<PathBuf>> = RwLock::new(None);
+/// Encoders of the ffmpeg in use, listed once instead of for every encode.
+/// Replaced by an empty cell when the directory changes.
+static ENCODERS: Lazy<RwLock<Arc<OnceCell<Vec<String>>>>> = Lazy::new(Default::default);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0} could not be found, install it or set its directory in the settings")]
+    NotFound(&'static str),
+    #[error("ffmpeg was built without the {0} encoder")]
+    MissingEncoder(&'static str),
+    #[error("unable to run ffmpeg: {0}")]
+    Spawn(#[from] std::io::Error),
+    #[error("ffmpeg failed: {0}")]
+    Failed(String),
+    #[error("unable to read the ffmpeg output: {0}")]
+    Parse(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Loudness measured by the first `loudnorm` pass.
+#[derive(Debug, Deserialize, PartialEq)]
+struct LoudnessMeasurement {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+/// Properties of the audio of a file, read from the output of ffmpeg.
+#[derive(Debug, Default, PartialEq)]
+struct AudioProperties {
+    /// In Hz.
+    sample_rate: Option<u32>,
+    /// In kbps, `None` for lossless audio.
+    bitrate: Option<u32>,
+}
+
+/// Sets the directory ffmpeg and ffprobe are taken from, `None` or an empty
+/// one to look them up in the `PATH`.
+pub fn set_directory(directory: Option<&str>) {
+    let directory = directory
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from);
+    let mut current = DIRECTORY.write().unwrap();
+
+    if *current != directory {
+        *current = directory;
+        *ENCODERS.write().unwrap() = Default::default();
+    }
+}
+
+/// Finds the executable of `binary`, such as `ffprobe`.
+pub fn locate(binary: &str) -> Option<PathBuf> {
+    let file_name = format!("{binary}{}", env::consts::EXE_SUFFIX);
+
+    if let Some(directory) = DIRECTORY.read().unwrap().as_ref() {
+        let path = directory.join(&file_name);
+        return path.is_file().then_some(path);
+    }
+
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|directory| directory.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+fn command(binary: &'static str) -> Result<Command> {
+    let mut command = Command::new(locate(binary).ok_or(Error::NotFound(binary))?);
+    command.arg("-hide_banner");
+
+    Ok(command)
+}
+
+fn ffmpeg() -> Result<Command> {
+    // Only ffmpeg reads commands from stdin, ffprobe rejects the option
+    let mut command = command("ffmpeg")?;
+    command.arg("-nostdin");
+
+    Ok(command)
+}
+
+/// Whether ffmpeg can be run.
+pub async fn is_available() -> bool {
+    version("ffmpeg").await.is_some()
+}
+
+/// First line of the `-version` output of `binary`, such as `ffprobe`.
+pub async fn version(binary: &'static str) -> Option<String> {
+    let output = run(command(binary).ok()?.arg("-version")).await.ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    stdout.lines().next().map(str::to_string)
+}
+
+/// Names of the encoders ffmpeg was built with.
+pub async fn encoders() -> Result<Vec<String>> {
+    let cell = ENCODERS.read().unwrap().clone();
+    let encoders = cell
+        .get_or_try_init(|| async {
+            let output = run(ffmpeg()?.arg("-encoders")).await?;
+
+            Ok::<_, Error>(parse_encoders(&String::from_utf8_lossy(&output.stdout)))
+        })
+        .await?;
+
+    Ok(encoders.clone())
+}
+
+/// Fails if ffmpeg cannot encode with `encoder`.
+async fn require_encoder(encoder: &'static str) -> Result<()> {
+    if encoders().await?.iter().any(|name| name == encoder) {
+        Ok(())
+    } else {
+        Err(Error::MissingEncoder(encoder))
+    }
+}
+
+/// Runs `command`, failing with the end of its error output if it does not succeed.
+async fn run(command: &mut Command) -> Result<Output> {
+    check_output(command.output().await?)
+}
+
+fn check_output(output: Output) -> Result<Output> {
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last_line = stderr.lines().last().unwrap_or_default();
+
+        return Err(Error::Failed(last_line.to_string()));
+    }
+
+    Ok(output)
+}
+
+/// An ffmpeg process encoding the audio written to it into a file.
+pub struct Encoder {
+    child: Child,
+    stdin: ChildStdin,
+}
+
+impl Encoder {
+    /// Starts encoding into `output` as `format`.
+    pub async fn spawn(
+        output: &Path,
+        format: &YoutubeFormat,
+        encoding: &AudioEncoding,
+    ) -> Result<Self> {
+        require_encoder(output_format(format).0).await?;
+
+        let mut child = ffmpeg()?
+            .args(["-loglevel", "error", "-i", "pipe:0", "-vn"])
+            .args(encoder_args(format, encoding))
+            .arg("-y")
+            .arg(output)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("Stdin should be piped");
+
+        Ok(Encoder { child, stdin })
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        self.stdin.write_all(chunk).await
+    }
+
+    /// Waits for the whole input to be encoded.
+    pub async fn finish(self) -> Result<()> {
+        // Closing stdin lets ffmpeg know the input is over
+        drop(self.stdin);
+        check_output(self.child.wait_with_output().await?)?;
+
+        Ok(())
+    }
+}
+
+/// Encoder, container and whether it is lossless, for `format`.
+fn output_format(format: &YoutubeFormat) -> (&'static str, &'static str, bool) {
+    match format {
+        YoutubeFormat::MP3 => ("libmp3lame", "mp3", false),
+        YoutubeFormat::WEBM => ("libopus", "webm", false),
+        YoutubeFormat::WAV => ("pcm_s16le", "wav", true),
+        YoutubeFormat::OGG => ("libvorbis", "ogg", false),
+        YoutubeFormat::FLAC => ("flac", "flac", true),
+        YoutubeFormat::M4A => ("aac", "ipod", false),
+        // The original stream is saved as is, this is only a fallback
+        YoutubeFormat::OPUS | YoutubeFormat::ORIGINAL => ("libopus", "opus", false),
+    }
+}
+
+/// Encoders needed by every [YoutubeFormat] that gets transcoded.
+pub fn required_encoders() -> Vec<&'static str> {
+    let mut encoders = Vec::new();
+
+    for format in [
+        YoutubeFormat::MP3,
+        YoutubeFormat::WEBM,
+        YoutubeFormat::WAV,
+        YoutubeFormat::OGG,
+        YoutubeFormat::FLAC,
+        YoutubeFormat::M4A,
+        YoutubeFormat::OPUS,
+    ] {
+        let encoder = output_format(&format).0;
+
+        if !encoders.contains(&encoder) {
+            encoders.push(encoder);
+        }
+    }
+
+    encoders
+}
+
+/// Codec and encoding arguments for `format`.
+fn encoder_args(format: &YoutubeFormat, encoding: &AudioEncoding) -> Vec<String> {
+    let (codec, container, lossless) = output_format(format);
+
+    let mut args = vec!["-c:a".to_string(), codec.to_string()];
+
+    // Vorbis qualities go from 0 (worst) to 10, the opposite of MP3
+    let vbr_quality = match (format, encoding.vbr_quality) {
+        (YoutubeFormat::MP3, Some(quality)) => Some(quality.min(9)),
+        (YoutubeFormat::OGG, Some(quality)) => Some(10 - quality.min(9)),
+        _ => None,
+    };
+
+    if let Some(quality) = vbr_quality {
+        args.extend(["-q:a".to_string(), quality.to_string()]);
+    } else if let (Some(bitrate), false) = (encoding.bitrate, lossless) {
+        args.extend(["-b:a".to_string(), format!("{bitrate}k")]);
+    }
+
+    if let Some(sample_rate) = encoding.sample_rate {
+        args.extend(["-ar".to_string(), sample_rate.to_string()]);
+    }
+
+    if let Some(channels) = encoding.channels {
+        args.extend(["-ac".to_string(), channels.to_string()]);
+    }
+
+    args.extend(["-f".to_string(), container.to_string()]);
+
+    args
+}
+
+/// Cuts the part of `input` between `start` and `end`, in seconds, into
+/// `output`.
+///
+/// The audio is encoded as `format`, or copied as is when it is `None`.
+pub async fn cut(
+    input: &Path,
+    output: &Path,
+    (start, end): (f32, f32),
+    format: Option<&YoutubeFormat>,
+    encoding: &AudioEncoding,
+) -> Result<()> {
+    let codec_args = match format {
+        Some(format) => {
+            require_encoder(output_format(format).0).await?;
+            encoder_args(format, encoding)
+        }
+        None => vec!["-c:a".to_string(), "copy".to_string()],
+    };
+
+    run(ffmpeg()?
+        .args(["-loglevel", "error"])
+        .args(["-ss", &start.to_string(), "-to", &end.to_string()])
+        .arg("-i")
+        .arg(input)
+        .arg("-vn")
+        .args(codec_args)
+        .arg("-y")
+        .arg(output))
+    .await?;
+
+    Ok(())
+}
+
+/// Normalises the loudness of the file at `path` following EBU R128.
+///
+/// The file is first analysed, then re-encoded with the measured values so
+/// the whole file gets the same linear gain.
+pub async fn normalize_loudness(path: &Path, settings: &LoudnessNormalization) -> Result<()> {
+    let target = format!(
+        "I={}:TP={}:LRA={}",
+        settings.target_lufs, settings.true_peak, TARGET_LRA
+    );
+
+    let analysis = run(ffmpeg()?
+        .arg("-i")
+        .arg(path)
+        .args(["-af", &format!("loudnorm={target}:print_format=json")])
+        .args(["-vn", "-f", "null", "-"]))
+    .await?;
+    let analysis = String::from_utf8_lossy(&analysis.stderr);
+    let measurement = parse_loudness_measurement(&analysis)?;
+
+    let filter = format!(
+        "loudnorm={target}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+        measurement.input_i,
+        measurement.input_tp,
+        measurement.input_lra,
+        measurement.input_thresh,
+        measurement.target_offset
+    );
+
+    reencode(path, &filter, &parse_audio_properties(&analysis)).await
+}
+
+/// Removes the silence at the start and end of the file at `path`.
+pub async fn trim_silence(path: &Path, settings: &SilenceTrimming) -> Result<TrimmedSilence> {
+    let output = silencedetect(path, settings.threshold_db, settings.min_duration).await?;
+    let (duration, leading, trailing) = parse_edge_silences(&output)?;
+
+    // Nothing to trim, or nothing would be left
+    if leading + trailing == 0.0 || leading + trailing >= duration {
+        return Ok(TrimmedSilence::default());
+    }
+
+    let filter = format!(
+        "atrim=start={leading}:end={},asetpts=PTS-STARTPTS",
+        duration - trailing
+    );
+    reencode(path, &filter, &parse_audio_properties(&output)).await?;
+
+    Ok(TrimmedSilence {
+        leading: leading as f32,
+        trailing: trailing as f32,
+    })
+}
+
+/// Finds the silences of the file at `path`, returning its duration and the
+/// silences as `(start, end)` pairs, in seconds.
+pub async fn detect_silences(
+    path: &Path,
+    threshold_db: f32,
+    min_duration: f32,
+) -> Result<(f64, Vec<(f64, f64)>)> {
+    parse_silences(&silencedetect(path, threshold_db, min_duration).await?)
+}
+
+/// Runs `silencedetect` on the file at `path`, returning its output.
+async fn silencedetect(path: &Path, threshold_db: f32, min_duration: f32) -> Result<String> {
+    let detection = run(ffmpeg()?
+        .arg("-i")
+        .arg(path)
+        .args([
+            "-af",
+            &format!("silencedetect=noise={threshold_db}dB:d={min_duration}"),
+        ])
+        .args(["-vn", "-f", "null", "-"]))
+    .await?;
+
+    Ok(String::from_utf8_lossy(&detection.stderr).into_owned())
+}
+
+/// Decodes the whole file at `path`, returning its duration in seconds and
+/// the volume of its loudest sample in dB, if it has any audio.
+///
+/// Decoding stops at the first error, so broken files fail instead.
+pub async fn measure_volume(path: &Path) -> Result<(f64, Option<f64>)> {
+    let detection = run(ffmpeg()?.args(["-xerror", "-i"]).arg(path).args([
+        "-af",
+        "volumedetect",
+        "-vn",
+        "-f",
+        "null",
+        "-",
+    ]))
+    .await?;
+
+    parse_volume(&String::from_utf8_lossy(&detection.stderr))
+}
+
+/// Writes `metadata` as the tags of the file at `path`, copying its streams
+/// as they are.
+///
+/// Used for formats lofty cannot write, such as WebM.
+pub async fn write_metadata(path: &Path, metadata: &[(&str, String)]) -> Result<()> {
+    let output_path = temporary_path(path);
+    let mut command = ffmpeg()?;
+    command
+        .arg("-i")
+        .arg(path)
+        .args(["-map", "0", "-c", "copy"]);
+    for (key, value) in metadata {
+        command.arg("-metadata").arg(format!("{key}={value}"));
+    }
+
+    let result = run(command.arg("-y").arg(&output_path)).await;
+
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_file(&output_path).await;
+        return Err(err);
+    }
+
+    tokio::fs::rename(&output_path, path).await?;
+
+    Ok(())
+}
+
+/// Re-encodes the file at `path` in place through `audio_filter`, keeping its
+/// tags, cover, sample rate and bitrate.
+async fn reencode(path: &Path, audio_filter: &str, properties: &AudioProperties) -> Result<()> {
+    // loudnorm resamples to 192kHz, the original rate has to be given back
+    let sample_rate = properties.sample_rate.unwrap_or(44100);
+    let output_path = temporary_path(path);
+
+    let mut command = ffmpeg()?;
+    command
+        .arg("-i")
+        .arg(path)
+        .args(["-map", "0", "-map_metadata", "0", "-c:v", "copy"])
+        .args(["-af", audio_filter])
+        .args(["-ar", &sample_rate.to_string()]);
+
+    // Encoders default to a low bitrate, which would lose the quality the
+    // file was downloaded or encoded in
+    if let Some(bitrate) = properties.bitrate {
+        command.args(["-b:a", &format!("{bitrate}k")]);
+    }
+
+    let result = run(command.arg("-y").arg(&output_path)).await;
+
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_file(&output_path).await;
+        return Err(err);
+    }
+
+    tokio::fs::rename(&output_path, path).await?;
+
+    Ok(())
+}
+
+/// Reads the encoder names listed by `ffmpeg -encoders`.
+fn parse_encoders(output: &str) -> Vec<String> {
+    // The list starts after a legend ending with a line of dashes
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Path used while re-encoding `path`, with the same extension so ffmpeg
+/// picks the same format.
+fn temporary_path(path: &Path) -> PathBuf {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_extension(format!("processing.{extension}"))
+}
+
+/// Reads the JSON block printed by `loudnorm` at the end of the output.
+fn parse_loudness_measurement(output: &str) -> Result<LoudnessMeasurement> {
+    let start = output.rfind('{');
+    let end = output.rfind('}');
+
+    let (Some(start), Some(end)) = (start, end) else {
+        return Err(Error::Parse("no loudness measurement found".to_string()));
+    };
+
+    serde_json::from_str(&output[start..=end]).map_err(|err| Error::Parse(err.to_string()))
+}
+
+/// Reads the duration of the input file from the output of ffmpeg, in seconds.
+fn parse_duration(output: &str) -> Result<f64> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Duration: "))
+        .and_then(|line| line.split(',').next())
+        .and_then(parse_timestamp)
+        .ok_or(Error::Parse("no duration found".to_string()))
+}
+
+/// Reads the properties of the first audio stream of the input file from the
+/// output of ffmpeg.
+fn parse_audio_properties(output: &str) -> AudioProperties {
+    let Some(stream) = output
+        .lines()
+        .find_map(|line| line.split_once("Audio: ").map(|(_, rest)| rest))
+    else {
+        return AudioProperties::default();
+    };
+
+    let mut properties = AudioProperties::default();
+    for part in stream.split(',').map(str::trim) {
+        if let Some((sample_rate, _)) = part.split_once(" Hz") {
+            properties.sample_rate = sample_rate.parse().ok();
+        } else if let Some((bitrate, _)) = part.split_once(" kb/s") {
+            properties.bitrate = bitrate.parse().ok();
+        }
+    }
+
+    // Streams of some containers, such as WebM, only have the overall bitrate
+    if properties.bitrate.is_none() {
+        properties.bitrate = output
+            .lines()
+            .find_map(|line| value_after(line, "bitrate: "))
+            .map(|bitrate| bitrate as u32);
+    }
+
+    let codec = stream.split_whitespace().next().unwrap_or_default();
+    let codec = codec.trim_end_matches(',');
+    if codec == "flac" || codec == "alac" || codec.starts_with("pcm_") {
+        properties.bitrate = None;
+    }
+
+    properties
+}
+
+/// Reads how much of the input file was decoded from the last progress line
+/// of ffmpeg, in seconds.
+///
+/// Unlike the duration in the headers, it tells whether the file is truncated.
+fn parse_decoded_duration(output: &str) -> Result<f64> {
+    // Progress lines are separated by carriage returns
+    output
+        .rsplit(['\r', '\n'])
+        .find_map(|line| line.split_once("time=").map(|(_, rest)| rest))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(parse_timestamp)
+        .ok_or(Error::Parse("no decoded duration found".to_string()))
+}
+
+/// Reads the `silencedetect` output, returning the duration of the file and
+/// its silences as `(start, end)` pairs, in seconds.
+fn parse_silences(output: &str) -> Result<(f64, Vec<(f64, f64)>)> {
+    let duration = parse_duration(output)?;
+
+    // A missing end means the silence lasts until the end of the file
+    let mut silences: Vec<(f64, Option<f64>)> = Vec::new();
+    for line in output.lines() {
+        if let Some(start) = value_after(line, "silence_start: ") {
+            silences.push((start, None));
+        } else if let Some(end) = value_after(line, "silence_end: ") {
+            if let Some(last) = silences.last_mut() {
+                last.1 = Some(end);
+            }
+        }
+    }
+
+    let silences = silences
+        .into_iter()
+        .map(|(start, end)| (start, end.unwrap_or(duration)))
+        .collect();
+
+    Ok((duration, silences))
+}
+
+/// Reads the `silencedetect` output, returning the duration of the file and
+/// of the silences at its start and end, in seconds.
+fn parse_edge_silences(output: &str) -> Result<(f64, f64, f64)> {
+    let (duration, silences) = parse_silences(output)?;
+
+    let leading = match silences.first() {
+        Some(&(start, end)) if start <= SILENCE_EDGE_MARGIN => end,
+        _ => 0.0,
+    };
+    let trailing = match silences.last() {
+        Some(&(start, end)) if end >= duration - SILENCE_EDGE_MARGIN => {
+            duration - start.max(leading)
+        }
+        _ => 0.0,
+    };
+
+    Ok((duration, leading, trailing))
+}
+
+/// Reads the `volumedetect` output, returning the decoded duration of the
+/// file and its maximum volume.
+fn parse_volume(output: &str) -> Result<(f64, Option<f64>)> {
+    let duration = parse_decoded_duration(output)?;
+    let max_volume = output
+        .lines()
+        .find_map(|line| value_after(line, "max_volume: "));
+
+    Ok((duration, max_volume))
+}
+
+/// Reads the number following `prefix` in `line`.
+fn value_after(line: &str, prefix: &str) -> Option<f64> {
+    let (_, rest) = line.split_once(prefix)?;
+
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+/// Parses timestamps such as `00:03:32.21` into seconds.
+fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    timestamp.trim().split(':').try_fold(0.0, |total, part| {
+        Some(total * 60.0 + part.parse::<f64>().ok()?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_loudness_measurement() {
+        let output = r#"
+size=N/A time=00:03:32.21 bitrate=N/A speed= 312x
+[Parsed_loudnorm_0 @ 0x600000c5c000]
+{
+	"input_i" : "-9.41",
+	"input_tp" : "0.21",
+	"input_lra" : "5.20",
+	"input_thresh" : "-19.56",
+	"output_i" : "-14.07",
+	"output_tp" : "-1.00",
+	"output_lra" : "4.50",
+	"output_thresh" : "-24.15",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.07"
+}
+"#;
+
+        assert_eq!(
+            parse_loudness_measurement(output).expect("Output should be valid"),
+            LoudnessMeasurement {
+                input_i: "-9.41".to_string(),
+                input_tp: "0.21".to_string(),
+                input_lra: "5.20".to_string(),
+                input_thresh: "-19.56".to_string(),
+                target_offset: "0.07".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn fails_without_measurement() {
+        assert!(parse_loudness_measurement("ffmpeg version 6.0").is_err());
+    }
+
+    #[test]
+    fn parses_edge_silences() {
+        let output = "
+  Duration: 00:03:30.00, start: 0.000000, bitrate: 128 kb/s
+[silencedetect @ 0x1] silence_start: 0
+[silencedetect @ 0x1] silence_end: 2.5 | silence_duration: 2.5
+[silencedetect @ 0x1] silence_start: 100.2
+[silencedetect @ 0x1] silence_end: 101.4 | silence_duration: 1.2
+[silencedetect @ 0x1] silence_start: 206
+";
+
+        assert_eq!(
+            parse_edge_silences(output).expect("Output should be valid"),
+            (210.0, 2.5, 4.0)
+        );
+    }
+
+    #[test]
+    fn ignores_silences_in_the_middle() {
+        let output = "
+  Duration: 00:01:00.00, start: 0.000000, bitrate: 128 kb/s
+[silencedetect @ 0x1] silence_start: 20
+[silencedetect @ 0x1] silence_end: 22 | silence_duration: 2
+";
+
+        assert_eq!(
+            parse_edge_silences(output).expect("Output should be valid"),
+            (60.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn parses_volume() {
+        let output = "
+  Duration: 00:03:30.00, start: 0.000000, bitrate: 128 kb/s
+size=N/A time=00:01:40.00 bitrate=N/A speed= 200x\r\
+size=N/A time=00:03:30.00 bitrate=N/A speed= 210x
+[Parsed_volumedetect_0 @ 0x1] n_samples: 18522000
+[Parsed_volumedetect_0 @ 0x1] mean_volume: -16.2 dB
+[Parsed_volumedetect_0 @ 0x1] max_volume: -0.4 dB
+";
+
+        assert_eq!(
+            parse_volume(output).expect("Output should be valid"),
+            (210.0, Some(-0.4))
+        );
+        assert_eq!(
+            parse_volume("size=N/A time=00:00:10.00 bitrate=N/A").expect("Output should be valid"),
+            (10.0, None)
+        );
+    }
+
+    #[test]
+    fn reads_the_decoded_duration_over_the_header() {
+        let output = "
+  Duration: 00:03:30.00, start: 0.000000, bitrate: 128 kb/s
+size=N/A time=00:00:50.00 bitrate=N/A speed= 100x\r\
+size=N/A time=00:01:12.50 bitrate=N/A speed= 110x
+";
+
+        assert_eq!(
+            parse_decoded_duration(output).expect("Output should be valid"),
+            72.5
+        );
+        assert!(parse_decoded_duration("  Duration: 00:03:30.00, start: 0.000000").is_err());
+    }
+
+    #[test]
+    fn reads_the_properties_of_the_audio_stream() {
+        let mp3 = "
+  Duration: 00:03:30.00, start: 0.025057, bitrate: 321 kb/s
+  Stream #0:0: Audio: mp3 (mp3float), 44100 Hz, stereo, fltp, 320 kb/s
+  Stream #0:1: Video: mjpeg (Baseline), yuvj420p, 600x600, 90k tbr
+";
+        let webm = "
+  Duration: 00:03:30.00, start: -0.007000, bitrate: 135 kb/s
+  Stream #0:0(eng): Audio: opus, 48000 Hz, stereo, fltp (default)
+";
+        let flac = "
+  Duration: 00:03:30.00, start: 0.000000, bitrate: 912 kb/s
+  Stream #0:0: Audio: flac, 44100 Hz, stereo, s16
+";
+
+        let properties = |sample_rate, bitrate| AudioProperties {
+            sample_rate: Some(sample_rate),
+            bitrate,
+        };
+        assert_eq!(parse_audio_properties(mp3), properties(44100, Some(320)));
+        assert_eq!(parse_audio_properties(webm), properties(48000, Some(135)));
+        assert_eq!(parse_audio_properties(flac), properties(44100, None));
+        assert_eq!(parse_audio_properties(""), AudioProperties::default());
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("00:03:32.25"), Some(212.25));
+        assert_eq!(parse_timestamp("1:02:03"), Some(3723.0));
+        assert_eq!(parse_timestamp("N/A"), None);
+    }
+
+    #[test]
+    fn maps_bitrate_onto_encoder_args() {
+        let encoding = AudioEncoding {
+            bitrate: Some(320),
+            sample_rate: Some(48000),
+            channels: Some(2),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            encoder_args(&YoutubeFormat::MP3, &encoding),
+            [
+                "-c:a",
+                "libmp3lame",
+                "-b:a",
+                "320k",
+                "-ar",
+                "48000",
+                "-ac",
+                "2",
+                "-f",
+                "mp3"
+            ]
+        );
+        assert_eq!(
+            encoder_args(&YoutubeFormat::FLAC, &encoding),
+            ["-c:a", "flac", "-ar", "48000", "-ac", "2", "-f", "flac"]
+        );
+    }
+
+    #[test]
+    fn prefers_vbr_quality_when_supported() {
+        let encoding = AudioEncoding {
+            bitrate: Some(192),
+            vbr_quality: Some(2),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            encoder_args(&YoutubeFormat::MP3, &encoding),
+            ["-c:a", "libmp3lame", "-q:a", "2", "-f", "mp3"]
+        );
+        assert_eq!(
+            encoder_args(&YoutubeFormat::OGG, &encoding),
+            ["-c:a", "libvorbis", "-q:a", "8", "-f", "ogg"]
+        );
+        assert_eq!(
+            encoder_args(&YoutubeFormat::M4A, &encoding),
+            ["-c:a", "aac", "-b:a", "192k", "-f", "ipod"]
+        );
+    }
+
+    #[test]
+    fn keeps_extension_of_temporary_path() {
+        assert_eq!(
+            temporary_path(Path::new("/music/Song.mp3")),
+            PathBuf::from("/music/Song.processing.mp3")
+        );
+    }
+
+    #[test]
+    fn parses_encoders() {
+        let output = "Encoders:
+ V..... = Video
+ A..... = Audio
+ ------
+ V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC (codec h264)
+ A....D aac                  AAC (Advanced Audio Coding)
+ A....D libmp3lame           libmp3lame MP3 (MPEG audio layer 3) (codec mp3)
+";
+
+        assert_eq!(parse_encoders(output), ["libx264", "aac", "libmp3lame"]);
+    }
+
+    #[test]
+    fn lists_each_required_encoder_once() {
+        let encoders = required_encoders();
+
+        assert_eq!(
+            encoders,
+            [
+                "libmp3lame",
+                "libopus",
+                "pcm_s16le",
+                "libvorbis",
+                "flac",
+                "aac"
+            ]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::{sync::RwLock, time::Duration};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client,
+};
+
+use crate::{
+    config::HttpSettings,
+    proxy::{self, Proxy},
+};
+
+/// Client shared by every request made by the app itself, built from the
+/// default [HttpSettings] until [set_client] is first called.
+///
+/// Clones of a [Client] share its connection pool.
+static CLIENT: RwLock<Option<Client>> = RwLock::new(None);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid HTTP header {0}")]
+    InvalidHeader(String),
+    #[error("unable to create the HTTP client: {0}")]
+    Client(#[from] reqwest::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Replaces the shared client, see [build_client].
+pub fn set_client(client: Client) {
+    *CLIENT.write().unwrap() = Some(client);
+}
+
+/// The shared client.
+pub fn client() -> Client {
+    if let Some(client) = CLIENT.read().unwrap().as_ref() {
+        return client.clone();
+    }
+
+    CLIENT
+        .write()
+        .unwrap()
+        .get_or_insert_with(|| {
+            // The default settings are always valid
+            build_client(&HttpSettings::default(), proxy::current().as_ref()).unwrap_or_default()
+        })
+        .clone()
+}
+
+/// Builds a client with `settings`, going through `proxy`.
+pub fn build_client(settings: &HttpSettings, proxy: Option<&Proxy>) -> Result<Client> {
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(settings.connect_timeout.into()))
+        .read_timeout(Duration::from_secs(settings.read_timeout.into()))
+        .default_headers(headers(settings)?);
+
+    if !settings.user_agent.is_empty() {
+        builder = builder.user_agent(&settings.user_agent);
+    }
+
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy.reqwest()?);
+    }
+
+    Ok(builder.build()?)
+}
+
+fn headers(settings: &HttpSettings) -> Result<HeaderMap> {
+    settings
+        .headers
+        .iter()
+        .map(|header| {
+            let name = HeaderName::try_from(header.name.trim())
+                .map_err(|_| Error::InvalidHeader(header.name.clone()))?;
+            let value = HeaderValue::try_from(header.value.trim())
+                .map_err(|_| Error::InvalidHeader(header.name.clone()))?;
+
+            Ok((name, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::HttpHeader;
+
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let header = |name: &str| HttpHeader {
+            name: name.to_string(),
+            value: "value".to_string(),
+        };
+        let settings = HttpSettings {
+            headers: vec![header("X-Valid"), header("Not valid")],
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            headers(&settings),
+            Err(Error::InvalidHeader(name)) if name == "Not valid"
+        ));
+
+        let settings = HttpSettings {
+            headers: vec![header(" X-Valid ")],
+            ..Default::default()
+        };
+        assert_eq!(headers(&settings).unwrap()["x-valid"], "value");
+    }
+}
@@ -1,5 +1,19 @@
+pub mod albums;
+pub mod artwork;
+pub mod cache;
 pub mod config;
+pub mod diagnostics;
 pub mod downloaders;
 pub mod events;
+pub mod ffmpeg;
+pub mod http;
+pub mod lyrics;
 pub mod models;
 pub mod parsers;
+pub mod partial;
+pub mod proxy;
+pub mod splitting;
+pub mod tagging;
+#[cfg(test)]
+mod testing;
+pub mod verification;
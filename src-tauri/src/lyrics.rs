@@ -0,0 +1,611 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::{
+    config::LyricsSettings,
+    http,
+    models::music::{Song, SourceDownloader},
+    tagging::write_lyrics,
+};
+
+static DEEZER_AUTH_URL: &str = "https://auth.deezer.com/login/anonymous?jo=p&rto=c";
+static DEEZER_API_URL: &str = "https://pipe.deezer.com/api";
+static DEEZER_LYRICS_QUERY: &str = "query SynchronizedTrackLyrics($trackId: String!) {
+  track(trackId: $trackId) {
+    lyrics { text synchronizedLines { line milliseconds } }
+  }
+}";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unable to fetch the lyrics: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("unable to read the lyrics: {0}")]
+    Parse(String),
+    #[error("unable to write the lyrics: {0}")]
+    Write(#[from] lofty::error::LoftyError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Lyrics of a song, plain and/or synced.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lyrics {
+    pub plain: Option<String>,
+    pub synced: Vec<SyncedLine>,
+}
+
+/// A line of synced lyrics, starting `time` milliseconds into the song.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncedLine {
+    pub time: u32,
+    pub text: String,
+}
+
+impl Lyrics {
+    /// The plain lyrics, or the synced ones without their timestamps.
+    pub fn plain_text(&self) -> Option<String> {
+        if self.plain.is_some() {
+            return self.plain.clone();
+        }
+
+        if self.synced.is_empty() {
+            return None;
+        }
+
+        let lines: Vec<_> = self.synced.iter().map(|line| &line.text[..]).collect();
+
+        Some(lines.join("\n"))
+    }
+
+    /// The synced lyrics in the LRC format.
+    pub fn to_lrc(&self) -> String {
+        self.synced
+            .iter()
+            .map(|line| {
+                let centiseconds = line.time / 10;
+
+                format!(
+                    "[{:02}:{:02}.{:02}]{}\n",
+                    centiseconds / 6000,
+                    centiseconds / 100 % 60,
+                    centiseconds % 100,
+                    line.text
+                )
+            })
+            .collect()
+    }
+
+    /// Moves the synced lines `milliseconds` earlier, for files whose start
+    /// was trimmed. Lines that would start before the file start with it.
+    pub fn shift_earlier(&mut self, milliseconds: u32) {
+        for line in &mut self.synced {
+            line.time = line.time.saturating_sub(milliseconds);
+        }
+    }
+}
+
+/// A place lyrics can be found.
+#[async_trait]
+pub trait LyricsProvider: Send + Sync {
+    /// Finds the lyrics of `song`, `None` if this provider has none for it.
+    async fn lyrics(&self, song: &Song) -> Result<Option<Lyrics>>;
+}
+
+/// Asks each provider in turn, until one has lyrics.
+pub struct FallbackProvider {
+    providers: Vec<Box<dyn LyricsProvider>>,
+}
+
+impl FallbackProvider {
+    pub fn new(providers: Vec<Box<dyn LyricsProvider>>) -> Self {
+        FallbackProvider { providers }
+    }
+}
+
+impl Default for FallbackProvider {
+    /// Deezer lyrics, then Youtube captions.
+    fn default() -> Self {
+        FallbackProvider::new(vec![
+            Box::new(DeezerLyrics::new()),
+            Box::new(YoutubeCaptions::new()),
+        ])
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for FallbackProvider {
+    async fn lyrics(&self, song: &Song) -> Result<Option<Lyrics>> {
+        let mut last_error = None;
+
+        for provider in &self.providers {
+            match provider.lyrics(song).await {
+                Ok(Some(lyrics)) => return Ok(Some(lyrics)),
+                Ok(None) => {}
+                Err(err) => last_error = Some(err),
+            }
+        }
+
+        // A failing provider only matters if no other one had lyrics
+        last_error.map_or(Ok(None), Err)
+    }
+}
+
+/// Lyrics of Deezer tracks, from the API used by the Deezer apps.
+///
+/// The public API the Deezer client uses has no lyrics, this one needs an
+/// anonymous token. The download session cannot stand for it, as the
+/// downloader keeps its credentials to itself.
+pub struct DeezerLyrics {
+    token: Mutex<Option<String>>,
+}
+
+#[derive(Deserialize)]
+struct DeezerToken {
+    jwt: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeezerLyricsResponse {
+    text: Option<String>,
+    #[serde(default)]
+    synchronized_lines: Option<Vec<DeezerSyncedLine>>,
+}
+
+#[derive(Deserialize)]
+struct DeezerSyncedLine {
+    line: String,
+    milliseconds: u32,
+}
+
+impl DeezerLyrics {
+    pub fn new() -> Self {
+        DeezerLyrics {
+            token: Mutex::new(None),
+        }
+    }
+
+    async fn token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+
+        if let Some(token) = token.as_ref() {
+            return Ok(token.clone());
+        }
+
+        let new_token = http::client()
+            .get(DEEZER_AUTH_URL)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<DeezerToken>()
+            .await?
+            .jwt;
+        *token = Some(new_token.clone());
+
+        Ok(new_token)
+    }
+
+    async fn request_lyrics(&self, song: &Song) -> Result<reqwest::Response> {
+        Ok(http::client()
+            .post(DEEZER_API_URL)
+            .bearer_auth(self.token().await?)
+            .json(&json!({
+                "operationName": "SynchronizedTrackLyrics",
+                "variables": { "trackId": song.id },
+                "query": DEEZER_LYRICS_QUERY,
+            }))
+            .send()
+            .await?)
+    }
+}
+
+impl Default for DeezerLyrics {
+    fn default() -> Self {
+        DeezerLyrics::new()
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for DeezerLyrics {
+    async fn lyrics(&self, song: &Song) -> Result<Option<Lyrics>> {
+        if song.source != SourceDownloader::Deezer {
+            return Ok(None);
+        }
+
+        let mut response = self.request_lyrics(song).await?;
+
+        // Tokens expire, an expired one is replaced once
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            *self.token.lock().await = None;
+            response = self.request_lyrics(song).await?;
+        }
+
+        let body: serde_json::Value = response.error_for_status()?.json().await?;
+
+        parse_deezer_lyrics(&body)
+    }
+}
+
+fn parse_deezer_lyrics(body: &serde_json::Value) -> Result<Option<Lyrics>> {
+    let lyrics = &body["data"]["track"]["lyrics"];
+    if lyrics.is_null() {
+        return Ok(None);
+    }
+
+    let response =
+        DeezerLyricsResponse::deserialize(lyrics).map_err(|err| Error::Parse(err.to_string()))?;
+    let synced = response
+        .synchronized_lines
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|line| !line.line.is_empty())
+        .map(|line| SyncedLine {
+            time: line.milliseconds,
+            text: line.line,
+        })
+        .collect();
+
+    let plain = response.text.filter(|text| !text.is_empty());
+
+    if plain.is_none() && synced.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(Lyrics { plain, synced }))
+}
+
+/// Captions of Youtube videos, used as synced lyrics.
+///
+/// Captions written by the uploader are preferred over generated ones.
+pub struct YoutubeCaptions;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CaptionTrack {
+    base_url: String,
+    /// `asr` for generated captions.
+    kind: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CaptionEvents {
+    #[serde(default)]
+    events: Vec<CaptionEvent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CaptionEvent {
+    t_start_ms: u32,
+    #[serde(default)]
+    segs: Vec<CaptionSegment>,
+}
+
+#[derive(Deserialize)]
+struct CaptionSegment {
+    utf8: String,
+}
+
+impl YoutubeCaptions {
+    pub fn new() -> Self {
+        YoutubeCaptions
+    }
+}
+
+impl Default for YoutubeCaptions {
+    fn default() -> Self {
+        YoutubeCaptions::new()
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for YoutubeCaptions {
+    async fn lyrics(&self, song: &Song) -> Result<Option<Lyrics>> {
+        if song.source != SourceDownloader::Youtube {
+            return Ok(None);
+        }
+
+        let page = http::client()
+            .get(format!("https://www.youtube.com/watch?v={}&hl=en", song.id))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let Some(track) = caption_tracks(&page)
+            .into_iter()
+            .min_by_key(|track| track.kind.is_some())
+        else {
+            return Ok(None);
+        };
+
+        let events: CaptionEvents = http::client()
+            .get(format!("{}&fmt=json3", track.base_url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut synced = synced_lines(events);
+
+        // Songs cut from a video only get the captions of their part
+        if let Some(segment) = &song.segment {
+            let start = (segment.start * 1000.0) as u32;
+            let end = (segment.end * 1000.0) as u32;
+
+            synced = synced
+                .into_iter()
+                .filter(|line| (start..end).contains(&line.time))
+                .map(|line| SyncedLine {
+                    time: line.time - start,
+                    ..line
+                })
+                .collect();
+        }
+
+        if synced.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Lyrics {
+            plain: None,
+            synced,
+        }))
+    }
+}
+
+/// Reads the caption tracks listed in a watch page.
+fn caption_tracks(page: &str) -> Vec<CaptionTrack> {
+    static MARKER: &str = "\"captionTracks\":";
+
+    let Some(start) = page.find(MARKER) else {
+        return Vec::new();
+    };
+
+    // The list is followed by the rest of the page, only the first value is read
+    serde_json::Deserializer::from_str(&page[start + MARKER.len()..])
+        .into_iter::<Vec<CaptionTrack>>()
+        .next()
+        .and_then(|tracks| tracks.ok())
+        .unwrap_or_default()
+}
+
+fn synced_lines(events: CaptionEvents) -> Vec<SyncedLine> {
+    events
+        .events
+        .into_iter()
+        .filter_map(|event| {
+            let text: String = event.segs.into_iter().map(|seg| seg.utf8).collect();
+            let text = text.replace('\n', " ").trim().to_string();
+
+            (!text.is_empty()).then_some(SyncedLine {
+                time: event.t_start_ms,
+                text,
+            })
+        })
+        .collect()
+}
+
+/// Finds the lyrics of `song` and embeds them into the file at `path`, along
+/// with an `.lrc` file if asked to.
+///
+/// `trimmed_lead` is the number of milliseconds cut from the start of the
+/// file, the synced lyrics are moved earlier by as much.
+///
+/// Returns whether lyrics were embedded, files without tags only get the
+/// `.lrc` file.
+pub async fn add_lyrics(
+    path: &Path,
+    song: &Song,
+    trimmed_lead: u32,
+    settings: &LyricsSettings,
+    provider: &dyn LyricsProvider,
+) -> Result<bool> {
+    let Some(mut lyrics) = provider.lyrics(song).await? else {
+        return Ok(false);
+    };
+    lyrics.shift_earlier(trimmed_lead);
+
+    let written = write_lyrics(path, &lyrics)?;
+
+    if settings.write_lrc && !lyrics.synced.is_empty() {
+        tokio::fs::write(path.with_extension("lrc"), lyrics.to_lrc()).await?;
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the same lyrics for every song.
+    struct StaticLyrics(Option<Lyrics>);
+
+    #[async_trait]
+    impl LyricsProvider for StaticLyrics {
+        async fn lyrics(&self, _song: &Song) -> Result<Option<Lyrics>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    /// Fails for every song.
+    struct FailingLyrics;
+
+    #[async_trait]
+    impl LyricsProvider for FailingLyrics {
+        async fn lyrics(&self, _song: &Song) -> Result<Option<Lyrics>> {
+            Err(Error::Parse("unavailable".to_string()))
+        }
+    }
+
+    fn song() -> Song {
+        Song {
+            source: SourceDownloader::Youtube,
+            id: "dQw4w9WgXcQ".to_string(),
+            title: "Never Gonna Give You Up".to_string(),
+            artist: "Rick Astley".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn plain(text: &str) -> Lyrics {
+        Lyrics {
+            plain: Some(text.to_string()),
+            synced: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_next_provider() {
+        let provider = FallbackProvider::new(vec![
+            Box::new(FailingLyrics),
+            Box::new(StaticLyrics(None)),
+            Box::new(StaticLyrics(Some(plain("found")))),
+        ]);
+
+        let lyrics = provider
+            .lyrics(&song())
+            .await
+            .expect("Lyrics should be found");
+
+        assert_eq!(lyrics, Some(plain("found")));
+    }
+
+    #[tokio::test]
+    async fn reports_errors_when_nothing_is_found() {
+        let provider =
+            FallbackProvider::new(vec![Box::new(StaticLyrics(None)), Box::new(FailingLyrics)]);
+
+        assert!(provider.lyrics(&song()).await.is_err());
+    }
+
+    #[test]
+    fn formats_lrc() {
+        let lyrics = Lyrics {
+            plain: None,
+            synced: vec![
+                SyncedLine {
+                    time: 18_250,
+                    text: "We're no strangers to love".to_string(),
+                },
+                SyncedLine {
+                    time: 62_000,
+                    text: "Never gonna give you up".to_string(),
+                },
+            ],
+        };
+
+        assert_eq!(
+            lyrics.to_lrc(),
+            "[00:18.25]We're no strangers to love\n[01:02.00]Never gonna give you up\n"
+        );
+        assert_eq!(
+            lyrics.plain_text().as_deref(),
+            Some("We're no strangers to love\nNever gonna give you up")
+        );
+    }
+
+    #[test]
+    fn shifts_synced_lines_earlier() {
+        let mut lyrics = Lyrics {
+            plain: None,
+            synced: vec![
+                SyncedLine {
+                    time: 1_000,
+                    text: "Intro".to_string(),
+                },
+                SyncedLine {
+                    time: 18_250,
+                    text: "We're no strangers to love".to_string(),
+                },
+            ],
+        };
+
+        lyrics.shift_earlier(2_500);
+
+        let times: Vec<_> = lyrics.synced.iter().map(|line| line.time).collect();
+        assert_eq!(times, [0, 15_750]);
+    }
+
+    #[test]
+    fn parses_deezer_lyrics() {
+        let body = json!({
+            "data": { "track": { "lyrics": {
+                "text": "Hello\nWorld",
+                "synchronizedLines": [
+                    { "line": "Hello", "milliseconds": 1000 },
+                    { "line": "", "milliseconds": 1500 },
+                    { "line": "World", "milliseconds": 2000 },
+                ],
+            } } }
+        });
+
+        let lyrics = parse_deezer_lyrics(&body)
+            .expect("Lyrics should be valid")
+            .expect("Lyrics should be found");
+
+        assert_eq!(lyrics.plain.as_deref(), Some("Hello\nWorld"));
+        assert_eq!(lyrics.synced.len(), 2);
+
+        let body = json!({ "data": { "track": { "lyrics": null } } });
+        assert_eq!(
+            parse_deezer_lyrics(&body).expect("Body should be valid"),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_caption_tracks_from_the_page() {
+        let page = r#"var x = {"captions":{"playerCaptionsTracklistRenderer":{"captionTracks":[{"baseUrl":"https://www.youtube.com/api/timedtext?v=1","kind":"asr"},{"baseUrl":"https://www.youtube.com/api/timedtext?v=2"}],"audioTracks":[]}}};"#;
+
+        let tracks = caption_tracks(page);
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].kind.as_deref(), Some("asr"));
+        assert_eq!(
+            tracks[1].base_url,
+            "https://www.youtube.com/api/timedtext?v=2"
+        );
+    }
+
+    #[test]
+    fn reads_caption_events() {
+        let events = CaptionEvents {
+            events: vec![
+                CaptionEvent {
+                    t_start_ms: 0,
+                    segs: Vec::new(),
+                },
+                CaptionEvent {
+                    t_start_ms: 1200,
+                    segs: vec![
+                        CaptionSegment {
+                            utf8: "♪ We're no".to_string(),
+                        },
+                        CaptionSegment {
+                            utf8: "\nstrangers ♪".to_string(),
+                        },
+                    ],
+                },
+            ],
+        };
+
+        assert_eq!(
+            synced_lines(events),
+            [SyncedLine {
+                time: 1200,
+                text: "♪ We're no strangers ♪".to_string()
+            }]
+        );
+    }
+}
@@ -3,24 +3,28 @@
     windows_subsystem = "windows"
 )]
 
-use std::sync::Mutex;
+use std::{collections::HashMap, env, path::Path, sync::Mutex};
 
 use crossbeam_channel::unbounded;
 use prawnloader::{
+    albums::{JobAlbum, JobTrack},
+    cache::MetadataCache,
     config::Config,
-    downloaders::{
-        deezer::Downloader as DeezerDownloader,
-        youtube::{Downloader as YoutubeDownloader, YoutubeRequest},
-    },
+    diagnostics::{diagnose, Diagnostics},
+    downloaders::{DownloadRequest, SourceStatus, Sources},
     events::Event,
-    models::music::{Song, SourceDownloader},
-    parsers::{parse_id, ParsedId},
+    models::music::{Resolution, Song, SourceDownloader},
+    parsers::parse_id,
+    partial::remove_partial_files,
+    splitting::{self, SplitProposal},
+};
+use tauri::{
+    api::path::{cache_dir, download_dir},
+    Manager, State,
 };
-use tauri::{Manager, State};
 
 struct DownloadersState {
-    deezer_downloader: DeezerDownloader,
-    youtube_downloader: YoutubeDownloader,
+    sources: Sources,
 }
 
 struct ConfigState {
@@ -28,61 +32,136 @@ struct ConfigState {
 }
 
 #[tauri::command]
-async fn get_songs(url: String, state: State<'_, DownloadersState>) -> Result<Vec<Song>, String> {
+async fn get_songs(
+    url: String,
+    state: State<'_, DownloadersState>,
+    config_state: State<'_, Mutex<ConfigState>>,
+) -> Result<Resolution, String> {
+    let config = config_state.lock().unwrap().config.clone();
     let parsed_id = parse_id(&url)
         .await
         .map_err(|_| format!("Unable to parse URL\"{url}\""))?;
-    let songs: Vec<Song> = match parsed_id {
-        ParsedId::DeezerAlbum(id) => state
-            .deezer_downloader
-            .get_album_tracks(id)
-            .await
-            .ok_or(format!("Invalid album id {id}"))?,
-        ParsedId::DeezerTrack(id) => state
-            .deezer_downloader
-            .get_track(id)
-            .await
-            .map(|x| vec![x.into()])
-            .ok_or(format!("Invalid track id {id}"))?,
-        ParsedId::YoutubeVideo(id) => state
-            .youtube_downloader
-            .get_song(id)
-            .await
-            .map(|x| vec![x])
-            .ok_or(format!("Invalid video id"))?,
-        ParsedId::YoutubePlaylist(id) => state
-            .youtube_downloader
-            .get_playlist_songs(id)
-            .await
-            .ok_or(format!("Invalid playlist id"))?,
-    };
-
-    let songs = songs.into_iter().map(|track| Song::from(track)).collect();
-
-    Ok(songs)
+
+    state
+        .sources
+        .resolve(parsed_id, &config)
+        .await
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
 async fn request_download(
     song: Song,
+    job: Option<JobTrack>,
+    job_album: Option<JobAlbum>,
     state: State<'_, DownloadersState>,
     config_state: State<'_, Mutex<ConfigState>>,
 ) -> Result<(), String> {
-    match song.source {
-        SourceDownloader::Youtube => {
-            let format = config_state.lock().unwrap().config.youtube_format.clone();
-            state
-                .youtube_downloader
-                .request_download(YoutubeRequest { song, format })
-                .await
-                .map_err(|err| err.to_string())
-        }
-        SourceDownloader::Deezer => state
-            .deezer_downloader
-            .request_download(song)
-            .await
-            .map_err(|err| err.to_string()),
-    }
+    let config = config_state.lock().unwrap().config.clone();
+
+    state
+        .sources
+        .request_download(DownloadRequest { song, config, job }, job_album)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn remove_from_queue(
+    songs: Vec<Song>,
+    tracks: Vec<JobTrack>,
+    state: State<'_, DownloadersState>,
+    config_state: State<'_, Mutex<ConfigState>>,
+) -> Result<(), ()> {
+    let config = config_state.lock().unwrap().config.clone();
+    state.sources.remove(songs, tracks, &config).await;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn clear_queue(
+    songs: Vec<Song>,
+    tracks: Vec<JobTrack>,
+    state: State<'_, DownloadersState>,
+    config_state: State<'_, Mutex<ConfigState>>,
+) -> Result<(), ()> {
+    let config = config_state.lock().unwrap().config.clone();
+    state.sources.clear_queue(songs, tracks, &config).await;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn resume_downloads(state: State<'_, DownloadersState>) -> Result<(), ()> {
+    state.sources.resume();
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn clear_metadata_cache(state: State<'_, DownloadersState>) -> Result<(), String> {
+    state
+        .sources
+        .clear_cache()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn source_status(
+    state: State<'_, DownloadersState>,
+) -> Result<HashMap<SourceDownloader, SourceStatus>, ()> {
+    Ok(state.sources.statuses())
+}
+
+#[tauri::command]
+async fn diagnostics(state: State<'_, DownloadersState>) -> Result<Diagnostics, ()> {
+    Ok(diagnose(&state.sources).await)
+}
+
+#[tauri::command]
+async fn propose_split(
+    path: String,
+    config_state: State<'_, Mutex<ConfigState>>,
+) -> Result<SplitProposal, String> {
+    let settings = config_state
+        .lock()
+        .unwrap()
+        .config
+        .silence_splitting
+        .clone();
+
+    let path = splitting::downloaded_file(Path::new(&path), &settings)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    splitting::propose(&path, &settings)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn split_download(
+    path: String,
+    song: Song,
+    proposal: SplitProposal,
+    tracklist: String,
+    config_state: State<'_, Mutex<ConfigState>>,
+) -> Result<Vec<String>, String> {
+    let config = config_state.lock().unwrap().config.clone();
+    let path = splitting::downloaded_file(Path::new(&path), &config.silence_splitting)
+        .await
+        .map_err(|err| err.to_string())?;
+    let tracks = splitting::name_tracks(&proposal, &tracklist);
+
+    let paths = splitting::split(&path, &song, tracks, config.cover_size)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(paths
+        .into_iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
 }
 
 #[tauri::command]
@@ -91,7 +170,16 @@ fn get_config(state: State<'_, Mutex<ConfigState>>) -> Result<Config, ()> {
 }
 
 #[tauri::command]
-fn update_config(config: Config, state: State<'_, Mutex<ConfigState>>) -> Result<Config, String> {
+fn update_config(
+    config: Config,
+    state: State<'_, Mutex<ConfigState>>,
+    downloaders_state: State<'_, DownloadersState>,
+) -> Result<Config, String> {
+    downloaders_state
+        .sources
+        .apply_config(&config)
+        .map_err(|err| err.to_string())?;
+
     state.lock().as_mut().unwrap().config = config;
 
     // Return the modified config in case we need to do additional checks later
@@ -105,8 +193,19 @@ async fn main() {
             let (progress_tx, progress_rx) = unbounded();
             let (event_tx, event_rx) = unbounded();
 
-            let deezer_downloader = DeezerDownloader::new(progress_tx.clone());
-            let youtube_downloader = YoutubeDownloader::new(progress_tx.clone());
+            let config = Config::default();
+            let cache_directory = cache_dir()
+                .unwrap_or_else(env::temp_dir)
+                .join("prawnloader")
+                .join("metadata");
+            let sources = Sources::new(&config, MetadataCache::new(cache_directory), progress_tx);
+
+            // Files left half-written by a previous run are not songs
+            if let Some(directory) = download_dir() {
+                tokio::spawn(async move {
+                    let _ = remove_partial_files(&directory).await;
+                });
+            }
 
             let handle = app.handle();
 
@@ -124,31 +223,40 @@ async fn main() {
                     match event {
                         Event::Waiting(track) => handle.emit_all(event_name, track).unwrap(),
                         Event::Start(track) => handle.emit_all(event_name, track).unwrap(),
-                        Event::Finish(track) => handle.emit_all(event_name, track).unwrap(),
+                        Event::Finish(track, summary) => {
+                            handle.emit_all(event_name, (track, summary)).unwrap()
+                        }
                         Event::DownloadError(track, err_msg) => {
                             handle.emit_all(event_name, (track, err_msg)).unwrap()
                         }
                         Event::RemoveFromQueue(track) => {
                             handle.emit_all(event_name, track).unwrap()
                         }
+                        Event::SourceStatus(source, status) => {
+                            handle.emit_all(event_name, (source, status)).unwrap()
+                        }
+                        Event::Paused(reason) => handle.emit_all(event_name, reason).unwrap(),
                     }
                 }
             });
 
-            app.manage(DownloadersState {
-                deezer_downloader,
-                youtube_downloader,
-            });
+            app.manage(DownloadersState { sources });
 
-            app.manage(Mutex::new(ConfigState {
-                config: Config::default(),
-            }));
+            app.manage(Mutex::new(ConfigState { config }));
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             get_songs,
             request_download,
+            remove_from_queue,
+            clear_queue,
+            resume_downloads,
+            clear_metadata_cache,
+            source_status,
+            diagnostics,
+            propose_split,
+            split_download,
             get_config,
             update_config
         ])
@@ -1,22 +1,71 @@
+use std::fmt;
+
 use deezer::models::Track;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+use crate::{albums::JobId, config::PlaylistTag};
+
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Default)]
 #[ts(export)]
 pub struct Album {
     pub title: String,
     pub cover_url: String,
+    /// Main artist of the album, which may differ from the one of its songs.
+    pub artist: Option<String>,
+    pub total_tracks: Option<u32>,
+    pub genres: Vec<String>,
+    pub label: Option<String>,
 }
 
-#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+/// A release date, as precise as the source knows it.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[ts(export)]
+pub struct ReleaseDate {
+    pub year: u32,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+}
+
+impl ReleaseDate {
+    /// Parses dates such as `2023-02-17`, `2023-02` or `2023`, ignoring
+    /// anything after the day.
+    pub fn parse(date: &str) -> Option<Self> {
+        let mut parts = date.get(..10).unwrap_or(date).split('-');
+        let year = parts.next().filter(|year| year.len() == 4)?.parse().ok()?;
+        let month = parts.next().and_then(|month| month.parse().ok());
+        let day = month.and(parts.next()).and_then(|day| day.parse().ok());
+
+        Some(ReleaseDate { year, month, day })
+    }
+}
+
+impl fmt::Display for ReleaseDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}", self.year)?;
+
+        if let Some(month) = self.month {
+            write!(f, "-{month:02}")?;
+
+            if let Some(day) = self.day {
+                write!(f, "-{day:02}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[ts(export)]
 #[serde(rename_all = "lowercase")]
 pub enum SourceDownloader {
+    #[default]
     Youtube,
     Deezer,
 }
 
-#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Default)]
 #[ts(export)]
 pub struct Song {
     #[ts(inline)]
@@ -25,7 +74,172 @@ pub struct Song {
     pub title: String,
     pub album: Album,
     pub artist: String,
-    pub release_date: String,
+    /// Every artist of the song, including the main one.
+    pub contributors: Vec<String>,
+    pub release_date: Option<ReleaseDate>,
+    /// Duration, in seconds.
+    pub duration: Option<u32>,
+    /// Position of the song in its album or video.
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub isrc: Option<String>,
+    pub explicit: bool,
+    /// Playlist the song was resolved from.
+    pub playlist: Option<PlaylistEntry>,
+    /// Grouping tag, such as the playlist the song is part of.
+    pub grouping: Option<String>,
+    /// Chapters of the video, for Youtube songs.
+    pub chapters: Vec<Chapter>,
+    /// Part of the video the song is cut from, for songs split from chapters.
+    pub segment: Option<Segment>,
+}
+
+/// Place of a song in the playlist it was resolved from.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[ts(export)]
+pub struct PlaylistEntry {
+    pub title: String,
+    /// Channel or user the playlist belongs to.
+    pub owner: Option<String>,
+    pub cover_url: String,
+    /// Position of the song in the playlist, from 1.
+    pub position: u32,
+    /// Number of songs in the playlist.
+    pub total: u32,
+}
+
+/// A chapter of a Youtube video, in seconds.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[ts(export)]
+pub struct Chapter {
+    pub title: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+/// Part of a video a song is cut from, in seconds.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[ts(export)]
+pub struct Segment {
+    pub start: f32,
+    pub end: f32,
+    /// Position of the song in the video, from 0.
+    pub index: u32,
+    /// Number of songs the video is split into.
+    pub count: u32,
+}
+
+impl Song {
+    /// Page of the song on its source.
+    pub fn source_url(&self) -> String {
+        match self.source {
+            SourceDownloader::Youtube => format!("https://www.youtube.com/watch?v={}", self.id),
+            SourceDownloader::Deezer => format!("https://www.deezer.com/track/{}", self.id),
+        }
+    }
+
+    /// Writes the playlist the song was resolved from into its metadata, as
+    /// its album or its grouping.
+    ///
+    /// Songs split from chapters keep the video as their album, the playlist
+    /// is always their grouping.
+    pub fn tag_playlist(self, tag: PlaylistTag) -> Song {
+        let Some(playlist) = self.playlist.clone() else {
+            return self;
+        };
+
+        match tag {
+            PlaylistTag::Album if self.segment.is_none() => Song {
+                album: Album {
+                    title: playlist.title,
+                    cover_url: playlist.cover_url,
+                    artist: playlist.owner,
+                    total_tracks: Some(playlist.total),
+                    ..Default::default()
+                },
+                track_number: Some(playlist.position),
+                disc_number: None,
+                ..self
+            },
+            PlaylistTag::Album | PlaylistTag::Grouping => Song {
+                grouping: Some(playlist.title),
+                ..self
+            },
+        }
+    }
+
+    /// Splits a video into one song per chapter, the video becoming the album.
+    ///
+    /// Songs without chapters are returned as is.
+    pub fn split_chapters(self) -> Vec<Song> {
+        if self.chapters.is_empty() || self.segment.is_some() {
+            return vec![self];
+        }
+
+        let count = self.chapters.len() as u32;
+        let album = Album {
+            title: self.title.clone(),
+            artist: Some(self.artist.clone()),
+            total_tracks: Some(count),
+            ..self.album.clone()
+        };
+
+        self.chapters
+            .iter()
+            .enumerate()
+            .map(|(index, chapter)| {
+                let (artist, title) = parse_chapter_title(&chapter.title);
+
+                Song {
+                    title,
+                    album: album.clone(),
+                    artist: artist.unwrap_or_else(|| self.artist.clone()),
+                    duration: Some((chapter.end - chapter.start).round() as u32),
+                    track_number: Some(index as u32 + 1),
+                    chapters: Vec::new(),
+                    segment: Some(Segment {
+                        start: chapter.start,
+                        end: chapter.end,
+                        index: index as u32,
+                        count,
+                    }),
+                    ..self.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+/// A song that was found but could not be resolved.
+#[derive(TS, Debug, Serialize, Deserialize, Clone)]
+#[ts(export)]
+pub struct UnresolvedSong {
+    pub id: String,
+    pub title: String,
+    pub reason: String,
+}
+
+/// Every song an id resolved to, along with the ones that failed.
+#[derive(TS, Debug, Serialize, Deserialize, Clone, Default)]
+#[ts(export)]
+pub struct Resolution {
+    pub songs: Vec<Song>,
+    pub unresolved: Vec<UnresolvedSong>,
+    /// Album or playlist the songs are part of, if the id refers to one.
+    pub album: Option<Album>,
+    /// Album job the songs are downloaded as, see [crate::albums].
+    pub job: Option<JobId>,
+}
+
+impl From<Vec<Song>> for Resolution {
+    fn from(songs: Vec<Song>) -> Self {
+        Self {
+            songs,
+            unresolved: Vec::new(),
+            album: None,
+            job: None,
+        }
+    }
 }
 
 impl From<Track> for Song {
@@ -35,32 +249,79 @@ impl From<Track> for Song {
             id: track.id.to_string(),
             title: track.title,
             artist: track.artist.name,
+            contributors: track
+                .contributors
+                .into_iter()
+                .map(|contributor| contributor.name)
+                .collect(),
             album: Album {
                 title: track.album.title,
                 cover_url: track.album.cover,
+                ..Default::default()
             },
-            release_date: track.release_date,
+            release_date: ReleaseDate::parse(&track.release_date),
+            duration: Some(track.duration as u32),
+            track_number: Some(track.track_position as u32),
+            disc_number: Some(track.disk_number as u32),
+            isrc: Some(track.isrc).filter(|isrc| !isrc.is_empty()),
+            explicit: track.explicit_lyrics,
+            playlist: None,
+            grouping: None,
+            chapters: Vec::new(),
+            segment: None,
+        }
+    }
+}
+
+impl From<&deezer::models::Album> for Album {
+    fn from(album: &deezer::models::Album) -> Self {
+        Self {
+            title: album.title.clone(),
+            cover_url: album.cover.clone(),
+            artist: Some(album.artist.name.clone()),
+            total_tracks: Some(album.nb_tracks as u32),
+            genres: album
+                .genres
+                .iter()
+                .map(|genre| genre.name.clone())
+                .collect(),
+            label: Some(album.label.clone()).filter(|label| !label.is_empty()),
         }
     }
 }
 
 impl From<rusty_ytdl::search::Video> for Song {
     fn from(video: rusty_ytdl::search::Video) -> Self {
-        let thumbnail = video
-            .thumbnails
-            .first()
-            .map_or_else(String::default, |t| t.url.clone());
+        let thumbnail = largest_thumbnail(
+            video
+                .thumbnails
+                .iter()
+                .map(|t| (&t.url[..], t.width, t.height)),
+        );
 
         Self {
             source: SourceDownloader::Youtube,
             id: video.id,
             title: video.title,
             album: Album {
-                title: String::new(),
                 cover_url: thumbnail,
+                ..Default::default()
             },
+            contributors: vec![video.channel.name.clone()],
             artist: video.channel.name,
-            release_date: video.uploaded_at.unwrap_or_default(),
+            // Search results only have relative dates, such as "3 years ago"
+            release_date: video.uploaded_at.as_deref().and_then(ReleaseDate::parse),
+            // Durations of search results are in milliseconds, and 0 for live
+            // videos or when unknown
+            duration: Some((video.duration / 1000) as u32).filter(|&duration| duration > 0),
+            track_number: None,
+            disc_number: None,
+            isrc: None,
+            explicit: false,
+            playlist: None,
+            grouping: None,
+            chapters: Vec::new(),
+            segment: None,
         }
     }
 }
@@ -72,21 +333,227 @@ impl From<rusty_ytdl::VideoDetails> for Song {
             .map(|author| author.name)
             .unwrap_or_default();
         let album = Album {
-            title: String::new(),
-            cover_url: video_details
-                .thumbnails
-                .first()
-                .map(|t| t.url.clone())
-                .unwrap_or_default(),
+            cover_url: largest_thumbnail(
+                video_details
+                    .thumbnails
+                    .iter()
+                    .map(|t| (&t.url[..], t.width, t.height)),
+            ),
+            ..Default::default()
         };
+        let length: u32 = video_details.length_seconds.parse().unwrap_or_default();
+        let chapters = chapters_from_starts(
+            video_details
+                .chapters
+                .into_iter()
+                .map(|chapter| (chapter.title, chapter.start_time as f32)),
+            length as f32,
+        );
 
         Self {
             source: SourceDownloader::Youtube,
             id: video_details.video_id,
             title: video_details.title,
             album,
+            contributors: vec![artist.clone()],
             artist,
-            release_date: video_details.upload_date,
+            release_date: ReleaseDate::parse(&video_details.upload_date),
+            duration: Some(length).filter(|&length| length > 0),
+            track_number: None,
+            disc_number: None,
+            isrc: None,
+            // Age restricted videos are the closest thing to an explicit flag
+            explicit: video_details.age_restricted,
+            playlist: None,
+            grouping: None,
+            chapters,
+            segment: None,
         }
     }
 }
+
+/// Returns the URL of the thumbnail with the highest resolution, from
+/// `(url, width, height)` items.
+fn largest_thumbnail<'a>(thumbnails: impl Iterator<Item = (&'a str, u64, u64)>) -> String {
+    thumbnails
+        .max_by_key(|(_, width, height)| width * height)
+        .map_or_else(String::default, |(url, _, _)| url.to_string())
+}
+
+/// Builds chapters from `(title, start)` items, each ending where the next
+/// one starts and the last one at `length`.
+pub fn chapters_from_starts(
+    starts: impl Iterator<Item = (String, f32)>,
+    length: f32,
+) -> Vec<Chapter> {
+    let starts: Vec<_> = starts.collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, (title, start))| Chapter {
+            title: title.clone(),
+            start: *start,
+            end: starts.get(index + 1).map_or(length, |(_, next)| *next),
+        })
+        .filter(|chapter| chapter.end > chapter.start)
+        .collect()
+}
+
+/// Reads the artist and title of chapter names such as `03. Artist - Title`.
+///
+/// Leading track numbers are dropped, the artist is only found when the name
+/// is split by a dash.
+fn parse_chapter_title(name: &str) -> (Option<String>, String) {
+    let without_number = name.trim_start_matches(|c: char| c.is_ascii_digit());
+
+    // Numbers are only dropped when followed by a separator, they may be part of the title
+    let name = match without_number
+        .trim_start()
+        .strip_prefix(['.', ')', '-', ':'])
+    {
+        Some(rest) if without_number.len() < name.len() => rest.trim(),
+        _ => name.trim(),
+    };
+
+    match name.split_once(" - ") {
+        Some((artist, title)) if !artist.trim().is_empty() && !title.trim().is_empty() => {
+            (Some(artist.trim().to_string()), title.trim().to_string())
+        }
+        _ => (None, name.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_chapter_titles() {
+        assert_eq!(parse_chapter_title("Intro"), (None, "Intro".to_string()));
+        assert_eq!(
+            parse_chapter_title("03. Daft Punk - Da Funk"),
+            (Some("Daft Punk".to_string()), "Da Funk".to_string())
+        );
+        assert_eq!(
+            parse_chapter_title("1 - Intro"),
+            (None, "Intro".to_string())
+        );
+        assert_eq!(
+            parse_chapter_title("12) Outro"),
+            (None, "Outro".to_string())
+        );
+        assert_eq!(
+            parse_chapter_title("99 Luftballons"),
+            (None, "99 Luftballons".to_string())
+        );
+    }
+
+    #[test]
+    fn tags_playlists_as_album_or_grouping() {
+        let song = Song {
+            title: "Song".to_string(),
+            album: Album {
+                title: "Video album".to_string(),
+                cover_url: "video.jpg".to_string(),
+                label: Some("Label".to_string()),
+                ..Default::default()
+            },
+            artist: "Artist".to_string(),
+            track_number: Some(1),
+            playlist: Some(PlaylistEntry {
+                title: "Mix".to_string(),
+                owner: Some("Owner".to_string()),
+                cover_url: "playlist.jpg".to_string(),
+                position: 2,
+                total: 10,
+            }),
+            ..Default::default()
+        };
+
+        let as_album = song.clone().tag_playlist(PlaylistTag::Album);
+        assert_eq!(as_album.album.title, "Mix");
+        assert_eq!(as_album.album.cover_url, "playlist.jpg");
+        assert_eq!(as_album.album.artist.as_deref(), Some("Owner"));
+        assert_eq!(as_album.album.total_tracks, Some(10));
+        assert_eq!(as_album.album.label, None);
+        assert_eq!(as_album.track_number, Some(2));
+        assert_eq!(as_album.grouping, None);
+
+        let as_grouping = song.clone().tag_playlist(PlaylistTag::Grouping);
+        assert_eq!(as_grouping.album.title, "Video album");
+        assert_eq!(as_grouping.grouping.as_deref(), Some("Mix"));
+
+        let chapter = Song {
+            segment: Some(Segment {
+                start: 0.0,
+                end: 60.0,
+                index: 0,
+                count: 3,
+            }),
+            ..song
+        };
+        let chapter = chapter.tag_playlist(PlaylistTag::Album);
+        assert_eq!(chapter.album.title, "Video album");
+        assert_eq!(chapter.track_number, Some(1));
+        assert_eq!(chapter.grouping.as_deref(), Some("Mix"));
+    }
+
+    #[test]
+    fn parses_release_dates() {
+        let date = |year, month, day| Some(ReleaseDate { year, month, day });
+
+        assert_eq!(
+            ReleaseDate::parse("2023-02-17"),
+            date(2023, Some(2), Some(17))
+        );
+        assert_eq!(
+            ReleaseDate::parse("2009-10-24T23:57:33-07:00"),
+            date(2009, Some(10), Some(24))
+        );
+        assert_eq!(ReleaseDate::parse("1999"), date(1999, None, None));
+        assert_eq!(ReleaseDate::parse("3 years ago"), None);
+        assert_eq!(ReleaseDate::parse(""), None);
+    }
+
+    #[test]
+    fn formats_release_dates() {
+        let full = ReleaseDate {
+            year: 2023,
+            month: Some(2),
+            day: Some(7),
+        };
+        let year_only = ReleaseDate {
+            year: 1999,
+            month: None,
+            day: None,
+        };
+
+        assert_eq!(full.to_string(), "2023-02-07");
+        assert_eq!(year_only.to_string(), "1999");
+    }
+
+    #[test]
+    fn ends_chapters_at_the_next_one() {
+        let chapters = chapters_from_starts(
+            [("Intro".to_string(), 0.0), ("Outro".to_string(), 90.0)].into_iter(),
+            200.0,
+        );
+
+        assert_eq!(
+            chapters,
+            [
+                Chapter {
+                    title: "Intro".to_string(),
+                    start: 0.0,
+                    end: 90.0
+                },
+                Chapter {
+                    title: "Outro".to_string(),
+                    start: 90.0,
+                    end: 200.0
+                },
+            ]
+        );
+    }
+}
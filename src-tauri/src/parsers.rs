@@ -3,7 +3,11 @@ use std::{collections::HashMap, num::ParseIntError};
 use rusty_ytdl::{get_video_id, search::Playlist};
 use url::Url;
 
-use crate::downloaders::{DeezerId, YoutubeId, YoutubePlaylistId};
+use crate::{
+    downloaders::{DeezerId, YoutubeId, YoutubePlaylistId},
+    http,
+    models::music::SourceDownloader,
+};
 
 type ParseResult = std::result::Result<ParsedId, Error>;
 
@@ -21,9 +25,11 @@ pub enum Error {
     AlbumNotFound(DeezerId),
     #[error("unable to parse url {0}")]
     UnparsableUrl(#[from] url::ParseError),
+    #[error("unable to follow the link: {0}")]
+    Request(#[from] reqwest::Error),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParsedId {
     DeezerAlbum(DeezerId),
     DeezerTrack(DeezerId),
@@ -31,6 +37,16 @@ pub enum ParsedId {
     YoutubePlaylist(YoutubePlaylistId),
 }
 
+impl ParsedId {
+    /// The source able to resolve this id.
+    pub fn source(&self) -> SourceDownloader {
+        match self {
+            ParsedId::DeezerAlbum(_) | ParsedId::DeezerTrack(_) => SourceDownloader::Deezer,
+            ParsedId::YoutubeVideo(_) | ParsedId::YoutubePlaylist(_) => SourceDownloader::Youtube,
+        }
+    }
+}
+
 pub async fn parse_id(url: &str) -> ParseResult {
     static PARSERS: [fn(url: &Url) -> ParseResult; 2] = [parse_deezer, parse_youtube];
 
@@ -49,8 +65,9 @@ pub async fn parse_id(url: &str) -> ParseResult {
 ///
 /// # Errors
 ///
-/// This function will return an error if the string is not a valid URL.
-async fn normalize_url(url: &str) -> std::result::Result<Url, url::ParseError> {
+/// This function will return an error if the string is not a valid URL, or
+/// if it is a short link that cannot be followed.
+async fn normalize_url(url: &str) -> std::result::Result<Url, Error> {
     let mut url = Url::parse(url)?;
 
     match url.domain() {
@@ -58,7 +75,7 @@ async fn normalize_url(url: &str) -> std::result::Result<Url, url::ParseError> {
             let _ = url.set_host(Some("www.youtube.com"));
         }
         Some("deezer.page.link") => {
-            url = follow_redirects(url).await;
+            url = follow_redirects(url).await?;
         }
         _ => {}
     };
@@ -105,10 +122,9 @@ fn parse_deezer(url: &Url) -> ParseResult {
     }
 }
 
-pub async fn follow_redirects(url: Url) -> Url {
-    let client = reqwest::Client::new();
-    let response = client.get(url).send().await.unwrap();
-    response.url().to_owned()
+pub async fn follow_redirects(url: Url) -> reqwest::Result<Url> {
+    let response = http::client().get(url).send().await?;
+    Ok(response.url().to_owned())
 }
 
 #[cfg(test)]
@@ -195,9 +211,13 @@ mod tests {
     #[tokio::test]
     async fn follows_redirects() {
         let new_url =
-            &follow_redirects(Url::parse(DEEZER_PAGE_LINK_URL).expect("URL should be valid")).await;
+            &follow_redirects(Url::parse(DEEZER_PAGE_LINK_URL).expect("URL should be valid"))
+                .await
+                .expect("link should be followed");
         let target_url =
-            &follow_redirects(Url::parse(DEEZER_TRACK_URL).expect("URL should be valid")).await;
+            &follow_redirects(Url::parse(DEEZER_TRACK_URL).expect("URL should be valid"))
+                .await
+                .expect("link should be followed");
 
         assert_eq!(new_url.domain(), target_url.domain());
         assert_eq!(new_url.path(), target_url.path());
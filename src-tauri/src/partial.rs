@@ -0,0 +1,167 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Marker in the name of files that are still being written, specific to the
+/// app so files of other programs are never taken for leftovers.
+static PARTIAL_MARKER: &str = ".prawnloader-part.";
+
+/// A file written under a hidden temporary name next to its final path, and
+/// renamed to it once complete.
+///
+/// Dropping it before [PartialFile::finish] removes the temporary file, so
+/// errors and cancelled downloads leave nothing that looks like a song.
+#[derive(Debug)]
+pub struct PartialFile {
+    path: PathBuf,
+    target: PathBuf,
+    finished: bool,
+}
+
+impl PartialFile {
+    pub fn new(target: PathBuf) -> Self {
+        PartialFile {
+            path: partial_path(&target),
+            target,
+            finished: false,
+        }
+    }
+
+    /// Temporary path to write to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Moves the written file to its final path, returning it.
+    pub async fn finish(mut self) -> io::Result<PathBuf> {
+        tokio::fs::rename(&self.path, &self.target).await?;
+        self.finished = true;
+
+        Ok(self.target.clone())
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Hidden temporary path of `target`, keeping its extension so tools can
+/// still tell the format from it.
+pub fn partial_path(target: &Path) -> PathBuf {
+    let stem = target
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = target
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    target.with_file_name(format!(".{stem}{PARTIAL_MARKER}{extension}"))
+}
+
+fn is_partial(file_name: &str) -> bool {
+    file_name.starts_with('.') && file_name.contains(PARTIAL_MARKER)
+}
+
+/// Removes the temporary files left in `directory` and all its
+/// subdirectories by a previous run, such as in album folders or next to
+/// split files. Symbolic links are not followed.
+pub async fn remove_partial_files(directory: &Path) -> io::Result<()> {
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(current) = directories.pop() {
+        let mut entries = match tokio::fs::read_dir(&current).await {
+            Ok(entries) => entries,
+            // Only the download directory itself has to be readable
+            Err(_) if current != directory => continue,
+            Err(err) => return Err(err),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+
+            if file_type.is_dir() {
+                directories.push(entry.path());
+            } else if file_type.is_file() && is_partial(&entry.file_name().to_string_lossy()) {
+                let _ = tokio::fs::remove_file(entry.path()).await;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::temp_directory;
+
+    use super::*;
+
+    #[test]
+    fn hides_partial_files() {
+        let path = partial_path(Path::new("/music/Artist - Song.mp3"));
+
+        assert_eq!(
+            path,
+            Path::new("/music/.Artist - Song.prawnloader-part.mp3")
+        );
+        assert!(is_partial(".Artist - Song.prawnloader-part.mp3"));
+        assert!(is_partial(".Artist - Song.prawnloader-part.processing.mp3"));
+        assert!(!is_partial("Artist - Song.prawnloader-part.mp3"));
+        // Partial files of browsers and other downloaders
+        assert!(!is_partial(".Artist - Song.part.mp3"));
+        assert!(!is_partial(".hidden.mp3"));
+    }
+
+    #[tokio::test]
+    async fn removes_unfinished_files() {
+        let directory = temp_directory("partial-files");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+
+        let finished = PartialFile::new(directory.join("Finished.mp3"));
+        tokio::fs::write(finished.path(), b"audio").await.unwrap();
+        let finished = finished.finish().await.unwrap();
+
+        let dropped = PartialFile::new(directory.join("Dropped.mp3"));
+        tokio::fs::write(dropped.path(), b"audio").await.unwrap();
+        let dropped_path = dropped.path().to_path_buf();
+        drop(dropped);
+
+        assert!(finished.exists());
+        assert!(!dropped_path.exists());
+
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+
+    #[tokio::test]
+    async fn removes_leftovers_in_album_folders() {
+        let directory = temp_directory("partial-leftovers");
+        let album = directory.join("Artist - Album");
+        let nested = directory.join("Mixes").join("2023");
+        tokio::fs::create_dir_all(&album).await.unwrap();
+        tokio::fs::create_dir_all(&nested).await.unwrap();
+
+        let leftover = partial_path(&album.join("Song.mp3"));
+        let nested_leftover = partial_path(&nested.join("Mix - Part 1.mp3"));
+        let song = album.join("Song.mp3");
+        let foreign = album.join(".Other.part.mp3");
+        for path in [&leftover, &nested_leftover, &song, &foreign] {
+            tokio::fs::write(path, b"audio").await.unwrap();
+        }
+
+        remove_partial_files(&directory).await.unwrap();
+
+        assert!(!leftover.exists());
+        assert!(!nested_leftover.exists());
+        assert!(song.exists());
+        assert!(foreign.exists());
+
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+    }
+}
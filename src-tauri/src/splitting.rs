@@ -0,0 +1,452 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::api::path::download_dir;
+use ts_rs::TS;
+
+use crate::{
+    artwork::fetch_square_cover,
+    config::{AudioEncoding, SilenceSplitting},
+    downloaders::replace_illegal_characters,
+    ffmpeg,
+    models::music::{chapters_from_starts, Chapter, Song},
+    partial::PartialFile,
+    tagging::{ffmpeg_metadata, write_tags},
+};
+
+/// Shortest track a silence can split off, in seconds.
+static MIN_TRACK_LENGTH: f64 = 30.0;
+/// Distance under which a tracklist timestamp is moved to a detected gap, in seconds.
+static SNAP_DISTANCE: f32 = 5.0;
+/// Frames per second of cue sheet timestamps.
+static CUE_FRAMES: f32 = 75.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Ffmpeg(#[from] ffmpeg::Error),
+    #[error("unable to tag the file: {0}")]
+    Tagging(#[from] lofty::error::LoftyError),
+    #[error("silence splitting is disabled")]
+    Disabled,
+    #[error("{0} is not a downloaded file")]
+    NotDownloaded(PathBuf),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Tracks found in a file from its silences.
+#[derive(TS, Debug, Clone, Serialize, Deserialize)]
+#[ts(export)]
+pub struct SplitProposal {
+    /// Duration of the whole file, in seconds.
+    pub duration: f32,
+    pub tracks: Vec<Chapter>,
+}
+
+/// A line of a tracklist.
+#[derive(Debug, PartialEq)]
+struct TracklistEntry {
+    start: Option<f32>,
+    title: String,
+}
+
+/// Checks silence splitting is enabled and `path` is a file of the download
+/// directory, returning its canonical path.
+///
+/// The path comes from the frontend, this keeps splitting to downloads.
+pub async fn downloaded_file(path: &Path, settings: &SilenceSplitting) -> Result<PathBuf> {
+    if !settings.enabled {
+        return Err(Error::Disabled);
+    }
+
+    let directory = download_dir().ok_or_else(|| Error::NotDownloaded(path.to_path_buf()))?;
+    file_in(path, &directory).await
+}
+
+async fn file_in(path: &Path, directory: &Path) -> Result<PathBuf> {
+    let not_downloaded = || Error::NotDownloaded(path.to_path_buf());
+    let file = tokio::fs::canonicalize(path)
+        .await
+        .map_err(|_| not_downloaded())?;
+    let directory = tokio::fs::canonicalize(directory).await?;
+
+    if file.starts_with(&directory) && file.is_file() {
+        Ok(file)
+    } else {
+        Err(not_downloaded())
+    }
+}
+
+/// Proposes tracks for the file at `path`, split in the middle of its silences.
+pub async fn propose(path: &Path, settings: &SilenceSplitting) -> Result<SplitProposal> {
+    let (duration, silences) =
+        ffmpeg::detect_silences(path, settings.threshold_db, settings.min_duration).await?;
+
+    Ok(SplitProposal {
+        duration: duration as f32,
+        tracks: tracks_from_silences(duration, &silences),
+    })
+}
+
+fn tracks_from_silences(duration: f64, silences: &[(f64, f64)]) -> Vec<Chapter> {
+    let mut starts = vec![0.0];
+
+    for &(start, end) in silences {
+        let middle = (start + end) / 2.0;
+        let previous = starts.last().copied().unwrap_or_default();
+
+        // Silences at the edges, or too close to the previous gap, are not gaps
+        if middle - previous >= MIN_TRACK_LENGTH && duration - middle >= MIN_TRACK_LENGTH {
+            starts.push(middle);
+        }
+    }
+
+    chapters_from_starts(
+        starts
+            .iter()
+            .enumerate()
+            .map(|(index, &start)| (format!("Track {}", index + 1), start as f32)),
+        duration as f32,
+    )
+}
+
+/// Names the tracks of `proposal` from a tracklist, such as a video description.
+///
+/// When the tracklist has timestamps, they replace the proposed tracks and are
+/// moved to the closest gap. Otherwise each line names a proposed track in order.
+pub fn name_tracks(proposal: &SplitProposal, tracklist: &str) -> Vec<Chapter> {
+    let entries = parse_tracklist(tracklist);
+
+    if !entries.is_empty() && entries.iter().all(|entry| entry.start.is_some()) {
+        let gaps: Vec<_> = proposal.tracks.iter().map(|track| track.start).collect();
+
+        return chapters_from_starts(
+            entries.into_iter().map(|entry| {
+                let start = entry.start.unwrap_or_default();
+                (entry.title, snap(start, &gaps))
+            }),
+            proposal.duration,
+        );
+    }
+
+    let mut titles = entries.into_iter().map(|entry| entry.title);
+
+    proposal
+        .tracks
+        .iter()
+        .map(|track| Chapter {
+            title: titles.next().unwrap_or_else(|| track.title.clone()),
+            ..track.clone()
+        })
+        .collect()
+}
+
+/// Moves `start` to the closest of `gaps` if it is near enough.
+fn snap(start: f32, gaps: &[f32]) -> f32 {
+    gaps.iter()
+        .copied()
+        .filter(|gap| (gap - start).abs() <= SNAP_DISTANCE)
+        .min_by(|a, b| (a - start).abs().total_cmp(&(b - start).abs()))
+        .unwrap_or(start)
+}
+
+/// Reads a tracklist with one track per line.
+///
+/// If some lines have a timestamp, only those are kept as the others are
+/// usually the rest of a description.
+fn parse_tracklist(text: &str) -> Vec<TracklistEntry> {
+    let entries: Vec<_> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(parse_tracklist_line)
+        .collect();
+
+    if entries.iter().any(|entry| entry.start.is_some()) {
+        entries
+            .into_iter()
+            .filter(|entry| entry.start.is_some())
+            .collect()
+    } else {
+        entries
+    }
+}
+
+fn parse_tracklist_line(line: &str) -> TracklistEntry {
+    let timestamp = line.split_whitespace().find_map(|word| {
+        let trimmed = word.trim_matches(['[', ']', '(', ')']);
+        parse_tracklist_timestamp(trimmed).map(|start| (word, start))
+    });
+
+    let Some((word, start)) = timestamp else {
+        return TracklistEntry {
+            start: None,
+            title: line.to_string(),
+        };
+    };
+
+    let title = line
+        .replacen(word, "", 1)
+        .trim()
+        .trim_matches(['-', '–', '|', ':'])
+        .trim()
+        .to_string();
+
+    TracklistEntry {
+        start: Some(start),
+        title,
+    }
+}
+
+/// Parses timestamps such as `3:05` or `1:02:03` into seconds.
+fn parse_tracklist_timestamp(timestamp: &str) -> Option<f32> {
+    let parts: Vec<_> = timestamp.split(':').collect();
+
+    if !(2..=3).contains(&parts.len()) || parts[1..].iter().any(|part| part.len() != 2) {
+        return None;
+    }
+
+    parts.iter().try_fold(0.0, |total, part| {
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        Some(total * 60.0 + part.parse::<f32>().ok()?)
+    })
+}
+
+/// Cuts the file at `path` into `tracks`, next to it, and writes a cue sheet
+/// describing them for the original file.
+///
+/// Returns the paths of the written files.
+pub async fn split(
+    path: &Path,
+    song: &Song,
+    tracks: Vec<Chapter>,
+    cover_size: u32,
+) -> Result<Vec<PathBuf>> {
+    let directory = path.parent().unwrap_or(Path::new("."));
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let songs = Song {
+        chapters: tracks,
+        segment: None,
+        ..song.clone()
+    }
+    .split_chapters();
+
+    // A missing cover should not fail the whole split
+    let cover = fetch_square_cover(&song.album.cover_url, cover_size)
+        .await
+        .ok();
+
+    let mut written = Vec::new();
+    for track in &songs {
+        let Some(segment) = &track.segment else {
+            continue;
+        };
+        let file_name = format!(
+            "{:02} - {}.{extension}",
+            track.track_number.unwrap_or_default(),
+            replace_illegal_characters(&track.title)
+        );
+        let file = PartialFile::new(directory.join(file_name));
+
+        ffmpeg::cut(
+            path,
+            file.path(),
+            (segment.start, segment.end),
+            None,
+            &AudioEncoding::default(),
+        )
+        .await?;
+        // lofty cannot write WebM, ffmpeg tags it without the cover
+        if !write_tags(file.path(), track, cover.as_deref())? {
+            ffmpeg::write_metadata(file.path(), &ffmpeg_metadata(track)).await?;
+        }
+
+        written.push(file.finish().await?);
+    }
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let cue_path = path.with_extension("cue");
+    tokio::fs::write(&cue_path, cue_sheet(&file_name, song, &songs)).await?;
+    written.push(cue_path);
+
+    Ok(written)
+}
+
+/// Writes a cue sheet describing `tracks` in the file `file_name`.
+fn cue_sheet(file_name: &str, song: &Song, tracks: &[Song]) -> String {
+    let file_type = if file_name.ends_with(".mp3") {
+        "MP3"
+    } else {
+        "WAVE"
+    };
+
+    let mut sheet = format!(
+        "PERFORMER \"{}\"\nTITLE \"{}\"\nFILE \"{}\" {file_type}\n",
+        cue_text(&song.artist),
+        cue_text(&song.title),
+        cue_text(file_name),
+    );
+
+    for track in tracks {
+        let start = track.segment.as_ref().map_or(0.0, |segment| segment.start);
+
+        sheet.push_str(&format!(
+            "  TRACK {:02} AUDIO\n    TITLE \"{}\"\n    PERFORMER \"{}\"\n    INDEX 01 {}\n",
+            track.track_number.unwrap_or_default(),
+            cue_text(&track.title),
+            cue_text(&track.artist),
+            cue_timestamp(start),
+        ));
+    }
+
+    sheet
+}
+
+/// Makes `text` fit between the quotes of a cue sheet.
+fn cue_text(text: &str) -> String {
+    text.replace('"', "'")
+}
+
+/// Formats seconds as the `minutes:seconds:frames` of cue sheets.
+fn cue_timestamp(seconds: f32) -> String {
+    let frames = (seconds * CUE_FRAMES).round() as u32;
+    let per_second = CUE_FRAMES as u32;
+
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / per_second / 60,
+        frames / per_second % 60,
+        frames % per_second
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::temp_directory;
+
+    use super::*;
+
+    fn track(title: &str, start: f32, end: f32) -> Chapter {
+        Chapter {
+            title: title.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn splits_in_the_middle_of_gaps() {
+        let silences = [(0.0, 1.0), (179.0, 181.0), (190.0, 192.0), (399.0, 400.0)];
+
+        assert_eq!(
+            tracks_from_silences(400.0, &silences),
+            [track("Track 1", 0.0, 180.0), track("Track 2", 180.0, 400.0)]
+        );
+    }
+
+    #[test]
+    fn parses_tracklists() {
+        let description = "Full album, enjoy!\n\n0:00 Intro\n[3:05] - Song\n1:02:03 Outro\n";
+
+        assert_eq!(
+            parse_tracklist(description),
+            [
+                TracklistEntry {
+                    start: Some(0.0),
+                    title: "Intro".to_string()
+                },
+                TracklistEntry {
+                    start: Some(185.0),
+                    title: "Song".to_string()
+                },
+                TracklistEntry {
+                    start: Some(3723.0),
+                    title: "Outro".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            parse_tracklist("Intro\nOutro"),
+            [
+                TracklistEntry {
+                    start: None,
+                    title: "Intro".to_string()
+                },
+                TracklistEntry {
+                    start: None,
+                    title: "Outro".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn names_proposed_tracks() {
+        let proposal = SplitProposal {
+            duration: 400.0,
+            tracks: vec![track("Track 1", 0.0, 180.0), track("Track 2", 180.0, 400.0)],
+        };
+
+        assert_eq!(
+            name_tracks(&proposal, "Intro"),
+            [track("Intro", 0.0, 180.0), track("Track 2", 180.0, 400.0)]
+        );
+        assert_eq!(
+            name_tracks(&proposal, "0:00 Intro\n2:58 Outro"),
+            [track("Intro", 0.0, 180.0), track("Outro", 180.0, 400.0)]
+        );
+    }
+
+    #[test]
+    fn formats_cue_timestamps() {
+        assert_eq!(cue_timestamp(0.0), "00:00:00");
+        assert_eq!(cue_timestamp(185.5), "03:05:38");
+        assert_eq!(cue_timestamp(6000.0), "100:00:00");
+    }
+
+    #[tokio::test]
+    async fn only_splits_files_of_the_directory() {
+        let directory = temp_directory("split-downloads");
+        let album = directory.join("Album");
+        tokio::fs::create_dir_all(&album).await.unwrap();
+        let song = album.join("Song.mp3");
+        tokio::fs::write(&song, b"audio").await.unwrap();
+
+        let outside = temp_directory("split-outside");
+        tokio::fs::create_dir_all(&outside).await.unwrap();
+        tokio::fs::write(outside.join("Other.mp3"), b"audio")
+            .await
+            .unwrap();
+
+        assert!(file_in(&song, &directory).await.is_ok());
+        // Both directories are in the same temporary directory
+        let escaping = album
+            .join("../..")
+            .join(outside.file_name().unwrap())
+            .join("Other.mp3");
+        assert!(matches!(
+            file_in(&escaping, &directory).await,
+            Err(Error::NotDownloaded(_))
+        ));
+        assert!(matches!(
+            file_in(&album, &directory).await,
+            Err(Error::NotDownloaded(_))
+        ));
+
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let _ = tokio::fs::remove_dir_all(&outside).await;
+    }
+}
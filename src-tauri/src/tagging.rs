@@ -0,0 +1,265 @@
+use std::{fs::File, path::Path};
+
+use lofty::{
+    config::{ParseOptions, WriteOptions},
+    error::Result,
+    file::{AudioFile, FileType, TaggedFileExt},
+    id3::v2::{
+        BinaryFrame, Frame, FrameId, SyncTextContentType, SynchronizedTextFrame, TimestampFormat,
+        UnsynchronizedTextFrame,
+    },
+    mpeg::MpegFile,
+    picture::{MimeType, Picture, PictureType},
+    probe::Probe,
+    tag::{Accessor, ItemKey, ItemValue, Tag, TagExt, TagItem, TagType},
+    TextEncoding,
+};
+
+use crate::{lyrics::Lyrics, models::music::Song};
+
+/// Language of the lyrics frames, as it is unknown.
+static UNKNOWN_LANGUAGE: [u8; 3] = *b"XXX";
+
+/// Writes the metadata of `song` into the file at `path`.
+///
+/// The tag format depends on the file: ID3v2 for MP3, Vorbis comments for
+/// OGG, Opus and FLAC, RIFF INFO for WAV and MP4 atoms for M4A. Other files,
+/// such as WebM, are left untouched and `false` is returned, see
+/// [ffmpeg_metadata]. Existing tags are kept unless `song` replaces them.
+///
+/// `cover` is embedded as the front cover and must be a JPEG image.
+pub fn write_tags(path: &Path, song: &Song, cover: Option<&[u8]>) -> Result<bool> {
+    let probe = Probe::open(path)?.guess_file_type()?;
+
+    let Some(tag_type) = probe.file_type().and_then(tag_type_for) else {
+        return Ok(false);
+    };
+
+    let mut tagged_file = probe.read()?;
+
+    let mut tag = tagged_file
+        .tag(tag_type)
+        .cloned()
+        .unwrap_or_else(|| Tag::new(tag_type));
+    tag.set_title(song.title.clone());
+    tag.set_artist(song.artist.clone());
+    replace_all(&mut tag, ItemKey::TrackArtists, &song.contributors);
+
+    let album = &song.album;
+    if !album.title.is_empty() {
+        tag.set_album(album.title.clone());
+    }
+
+    if let Some(album_artist) = &album.artist {
+        tag.insert_text(ItemKey::AlbumArtist, album_artist.clone());
+    }
+
+    replace_all(&mut tag, ItemKey::Genre, &album.genres);
+
+    if let Some(label) = &album.label {
+        tag.insert_text(ItemKey::Label, label.clone());
+    }
+
+    if let Some(grouping) = &song.grouping {
+        tag.insert_text(ItemKey::ContentGroup, grouping.clone());
+    }
+
+    if let Some(date) = song.release_date {
+        tag.set_year(date.year);
+        tag.insert_text(ItemKey::RecordingDate, date.to_string());
+    }
+
+    if let Some(track_number) = song.track_number {
+        tag.set_track(track_number);
+    }
+
+    let total_tracks = album
+        .total_tracks
+        .or(song.segment.as_ref().map(|segment| segment.count));
+    if let Some(total_tracks) = total_tracks {
+        tag.set_track_total(total_tracks);
+    }
+
+    if let Some(disc_number) = song.disc_number {
+        tag.set_disk(disc_number);
+    }
+
+    if let Some(isrc) = &song.isrc {
+        tag.insert_text(ItemKey::Isrc, isrc.clone());
+    }
+
+    if song.explicit {
+        tag.insert_text(ItemKey::ParentalAdvisory, "1".to_string());
+    }
+
+    // Not every format has a dedicated field for it, the comment always works
+    let source_url = song.source_url();
+    tag.insert_text(ItemKey::AudioSourceUrl, source_url.clone());
+    tag.set_comment(source_url);
+
+    if let Some(cover) = cover {
+        tag.remove_picture_type(PictureType::CoverFront);
+        tag.push_picture(Picture::new_unchecked(
+            PictureType::CoverFront,
+            Some(MimeType::Jpeg),
+            None,
+            cover.to_vec(),
+        ));
+    }
+
+    tagged_file.insert_tag(tag);
+    tagged_file.save_to_path(path, WriteOptions::default())?;
+
+    Ok(true)
+}
+
+/// Metadata of `song` as ffmpeg names it, for the files [write_tags] leaves
+/// untouched. It has no place for the cover.
+pub fn ffmpeg_metadata(song: &Song) -> Vec<(&'static str, String)> {
+    let album = &song.album;
+    let mut metadata = vec![
+        ("title", song.title.clone()),
+        ("artist", song.artist.clone()),
+        ("comment", song.source_url()),
+    ];
+
+    if !album.title.is_empty() {
+        metadata.push(("album", album.title.clone()));
+    }
+
+    if let Some(album_artist) = &album.artist {
+        metadata.push(("album_artist", album_artist.clone()));
+    }
+
+    if !album.genres.is_empty() {
+        metadata.push(("genre", album.genres.join("; ")));
+    }
+
+    if let Some(grouping) = &song.grouping {
+        metadata.push(("grouping", grouping.clone()));
+    }
+
+    if let Some(date) = song.release_date {
+        metadata.push(("date", date.to_string()));
+    }
+
+    let total_tracks = album
+        .total_tracks
+        .or(song.segment.as_ref().map(|segment| segment.count));
+    let track = match (song.track_number, total_tracks) {
+        (Some(number), Some(total)) => Some(format!("{number}/{total}")),
+        (Some(number), None) => Some(number.to_string()),
+        _ => None,
+    };
+    if let Some(track) = track {
+        metadata.push(("track", track));
+    }
+
+    if let Some(disc_number) = song.disc_number {
+        metadata.push(("disc", disc_number.to_string()));
+    }
+
+    if let Some(isrc) = &song.isrc {
+        metadata.push(("isrc", isrc.clone()));
+    }
+
+    metadata
+}
+
+/// Replaces every value of `key` by `values`, as fields such as genres can
+/// hold several.
+fn replace_all(tag: &mut Tag, key: ItemKey, values: &[String]) {
+    if values.is_empty() {
+        return;
+    }
+
+    tag.remove_key(&key);
+    for value in values {
+        tag.push(TagItem::new(key.clone(), ItemValue::Text(value.clone())));
+    }
+}
+
+/// Adds `lyrics` to the tags of the file at `path`.
+///
+/// MP3 files get USLT and SYLT frames. Other formats have no standard field
+/// for synced lyrics, they get them in the LRC format when there are no plain
+/// lyrics. Other files, such as WebM, are left untouched and `false` is
+/// returned.
+pub fn write_lyrics(path: &Path, lyrics: &Lyrics) -> Result<bool> {
+    let probe = Probe::open(path)?.guess_file_type()?;
+
+    match probe.file_type() {
+        Some(FileType::Mpeg) => write_id3v2_lyrics(path, lyrics).map(|_| true),
+        Some(file_type) if tag_type_for(file_type).is_some() => {
+            let text = match lyrics.plain {
+                Some(ref plain) => plain.clone(),
+                None => lyrics.to_lrc(),
+            };
+
+            let mut tagged_file = probe.read()?;
+            if tagged_file.primary_tag().is_none() {
+                let tag_type = tagged_file.primary_tag_type();
+                tagged_file.insert_tag(Tag::new(tag_type));
+            }
+
+            let tag = tagged_file
+                .primary_tag_mut()
+                .expect("Tag should be inserted");
+            tag.insert_text(ItemKey::Lyrics, text);
+            tag.save_to_path(path, WriteOptions::default())?;
+
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn write_id3v2_lyrics(path: &Path, lyrics: &Lyrics) -> Result<()> {
+    let file = MpegFile::read_from(&mut File::open(path)?, ParseOptions::new())?;
+    let mut tag = file.id3v2().cloned().unwrap_or_default();
+
+    if let Some(text) = lyrics.plain_text() {
+        tag.insert(Frame::UnsynchronizedText(UnsynchronizedTextFrame::new(
+            TextEncoding::UTF8,
+            UNKNOWN_LANGUAGE,
+            String::new(),
+            text,
+        )));
+    }
+
+    if !lyrics.synced.is_empty() {
+        let content = lyrics
+            .synced
+            .iter()
+            .map(|line| (line.time, line.text.clone()))
+            .collect();
+        let frame = SynchronizedTextFrame::new(
+            TextEncoding::UTF8,
+            UNKNOWN_LANGUAGE,
+            TimestampFormat::MS,
+            SyncTextContentType::Lyrics,
+            None,
+            content,
+        );
+
+        // lofty has no dedicated frame variant for SYLT
+        tag.insert(Frame::Binary(BinaryFrame::new(
+            FrameId::new("SYLT")?,
+            frame.as_bytes()?,
+        )));
+    }
+
+    tag.save_to_path(path, WriteOptions::default())
+}
+
+fn tag_type_for(file_type: FileType) -> Option<TagType> {
+    match file_type {
+        FileType::Mpeg => Some(TagType::Id3v2),
+        FileType::Vorbis | FileType::Opus | FileType::Flac | FileType::Speex => {
+            Some(TagType::VorbisComments)
+        }
+        FileType::Wav => Some(TagType::RiffInfo),
+        FileType::Mp4 => Some(TagType::Mp4Ilst),
+        _ => None,
+    }
+}
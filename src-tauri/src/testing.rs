@@ -0,0 +1,16 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+static NEXT_DIRECTORY: AtomicU32 = AtomicU32::new(0);
+
+/// Path of a directory used by a single test, so tests running at the same
+/// time never share files. The directory is not created.
+pub fn temp_directory(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "prawnloader-{name}-{}-{}",
+        std::process::id(),
+        NEXT_DIRECTORY.fetch_add(1, Ordering::Relaxed)
+    ))
+}
@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use lofty::{error::LoftyError, file::AudioFile, probe::Probe};
+
+use crate::{config::Verification, downloaders::TrimmedSilence, ffmpeg, models::music::Song};
+
+/// Volume of the loudest sample under which a file is considered silent, in dB.
+static SILENT_VOLUME: f64 = -90.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("the file is empty")]
+    Empty,
+    #[error("the file cannot be read: {0}")]
+    Unreadable(#[from] LoftyError),
+    #[error("the file cannot be decoded: {0}")]
+    Undecodable(#[from] ffmpeg::Error),
+    #[error("the file lasts {actual:.1}s instead of {expected:.1}s")]
+    DurationMismatch { expected: f32, actual: f32 },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Whether downloading the file again may fix it, as opposed to problems
+    /// every download of the song would have.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Empty | Error::Undecodable(_) => true,
+            Error::DurationMismatch { expected, actual } => actual < expected,
+            _ => false,
+        }
+    }
+}
+
+/// Checks that the file at `path` is a complete download of `song`.
+///
+/// The headers are read first, then the whole file is decoded when ffmpeg is
+/// available, which also tells whether it is complete and has any sound.
+///
+/// Some songs are silent on purpose, so a silent file is only reported in the
+/// returned warnings.
+pub async fn verify(
+    path: &Path,
+    song: &Song,
+    trimmed: Option<TrimmedSilence>,
+    settings: &Verification,
+) -> Result<Vec<String>> {
+    if tokio::fs::metadata(path).await?.len() == 0 {
+        return Err(Error::Empty);
+    }
+
+    let header_duration = header_duration(path)?;
+    let measured = if ffmpeg::is_available().await {
+        Some(ffmpeg::measure_volume(path).await?)
+    } else {
+        None
+    };
+
+    let duration = measured
+        .map(|(duration, _)| duration as f32)
+        .or(header_duration);
+    if let (Some(actual), Some(expected)) = (duration, expected_duration(song, trimmed)) {
+        if (actual - expected).abs() > settings.duration_tolerance {
+            return Err(Error::DurationMismatch { expected, actual });
+        }
+    }
+
+    let mut warnings = Vec::new();
+    if let Some((_, max_volume)) = measured {
+        if !matches!(max_volume, Some(volume) if volume > SILENT_VOLUME) {
+            warnings.push("the file is silent".to_string());
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Duration of the file at `path` according to its headers, in seconds.
+///
+/// Formats without headers lofty knows of, such as WebM, have none.
+fn header_duration(path: &Path) -> Result<Option<f32>> {
+    let probe = Probe::open(path)?.guess_file_type()?;
+    if probe.file_type().is_none() {
+        return Ok(None);
+    }
+
+    let duration = probe.read()?.properties().duration();
+    if duration.is_zero() {
+        return Err(Error::Empty);
+    }
+
+    Ok(Some(duration.as_secs_f32()))
+}
+
+/// Duration the file of `song` should have, once its silence was trimmed.
+fn expected_duration(song: &Song, trimmed: Option<TrimmedSilence>) -> Option<f32> {
+    let trimmed = trimmed.map_or(0.0, |trimmed| trimmed.leading + trimmed.trailing);
+
+    song.duration.map(|duration| duration as f32 - trimmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{models::music::SourceDownloader, testing::temp_directory};
+
+    use super::*;
+
+    fn song(duration: Option<u32>) -> Song {
+        Song {
+            source: SourceDownloader::Deezer,
+            id: "id".to_string(),
+            title: "Title".to_string(),
+            artist: "Artist".to_string(),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn expects_trimmed_duration() {
+        let trimmed = TrimmedSilence {
+            leading: 1.5,
+            trailing: 2.5,
+        };
+
+        assert_eq!(expected_duration(&song(Some(200)), None), Some(200.0));
+        assert_eq!(
+            expected_duration(&song(Some(200)), Some(trimmed)),
+            Some(196.0)
+        );
+        assert_eq!(expected_duration(&song(None), Some(trimmed)), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_files() {
+        let directory = temp_directory("empty-download");
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let path = directory.join("Song.mp3");
+        tokio::fs::write(&path, b"").await.unwrap();
+
+        let result = verify(&path, &song(Some(200)), None, &Verification::default()).await;
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+
+        assert!(matches!(result, Err(Error::Empty)));
+    }
+
+    #[test]
+    fn only_retries_incomplete_files() {
+        let mismatch = |actual| Error::DurationMismatch {
+            expected: 200.0,
+            actual,
+        };
+
+        assert!(Error::Empty.is_retryable());
+        assert!(mismatch(120.0).is_retryable());
+        assert!(!mismatch(260.0).is_retryable());
+        assert!(!Error::Io(std::io::ErrorKind::InvalidData.into()).is_retryable());
+    }
+}
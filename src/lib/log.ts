@@ -1,4 +1,6 @@
+import type { DownloadSummary } from '$models/DownloadSummary';
 import type { Song } from '$models/Song';
+import type { UnresolvedSong } from '$models/UnresolvedSong';
 import { writable, type Writable } from 'svelte/store';
 
 export class Log {
@@ -26,8 +28,19 @@ export function clearLogs() {
 	logs.set([]);
 }
 
-export function formatLogSuccess(song: Song): Log {
-	return new Log(true, `Downloaded ${song.artist} - ${song.title}`);
+export function formatLogSuccess(song: Song, summary: DownloadSummary): Log {
+	const details = [summary.format];
+	if (summary.quality !== null) details.push(summary.quality);
+	if (summary.trimmed !== null) {
+		const { leading, trailing } = summary.trimmed;
+		details.push(`trimmed ${leading.toFixed(1)}s / ${trailing.toFixed(1)}s`);
+	}
+	if (summary.lyrics) details.push('lyrics');
+
+	const suffix = details.length > 0 ? ` (${details.join(', ')})` : '';
+	const warnings = summary.warnings.map(warning => `, ${warning}`).join('');
+
+	return new Log(true, `Downloaded ${song.artist} - ${song.title}${suffix}${warnings}`);
 }
 
 export function formatLogAlbumNotFound(albumId: number): Log {
@@ -41,3 +54,7 @@ export function formatLogSongNotFound(songId: number): Log {
 export function formatLogDownloadError(song: Song, message: string): Log {
 	return new Log(false, `Error while downloading ${song.artist} - ${song.title} (${message}).`);
 }
+
+export function formatLogUnresolvedSong(unresolved: UnresolvedSong): Log {
+	return new Log(false, `Unable to resolve ${unresolved.title} (${unresolved.reason}).`);
+}
@@ -1,3 +1,6 @@
+import type { DownloadSummary } from '$models/DownloadSummary';
+import type { JobAlbum } from '$models/JobAlbum';
+import type { JobTrack } from '$models/JobTrack';
 import type { Song } from '$models/Song';
 
 type DownloadStatus = 'Downloading' | 'Inactive';
@@ -5,4 +8,18 @@ type DownloadStatus = 'Downloading' | 'Inactive';
 export interface QueueSong {
 	song: Song;
 	download_state: DownloadStatus;
+	/** Album job the song is downloaded as, into the folder of its album. */
+	job: JobTrack | null;
+	/** What the album job starts from, shared by every song of the job. */
+	job_album: JobAlbum | null;
+}
+
+/** Whether `a` and `b` are the same song, chapters of a video sharing its id. */
+export function isSameSong(a: Song, b: Song): boolean {
+	return a.id === b.id && a.segment?.index === b.segment?.index;
+}
+
+export interface FinishedSong {
+	song: Song;
+	summary: DownloadSummary;
 }
@@ -1,4 +1,10 @@
-import type { QueueSong } from './music';
+import type { FinishedSong, QueueSong } from './music';
 import { writable, type Writable } from 'svelte/store';
 
 export const queue: Writable<QueueSong[]> = writable([]);
+
+/** Youtube songs downloaded whole, which can still be split into tracks. */
+export const splittable: Writable<FinishedSong[]> = writable([]);
+
+/** Reason the downloads were paused for, while they are. */
+export const paused: Writable<string | null> = writable(null);
@@ -7,7 +7,14 @@ import {
 import type { Event } from '$models/Event';
 import { invoke } from '@tauri-apps/api';
 import type { Song } from '$models/Song';
+import type { JobAlbum } from '$models/JobAlbum';
+import type { JobTrack } from '$models/JobTrack';
+import type { Resolution } from '$models/Resolution';
 import type { Config } from '$models/Config';
+import type { SourceDownloader } from '$models/SourceDownloader';
+import type { SourceStatus } from '$models/SourceStatus';
+import type { Diagnostics } from '$models/Diagnostics';
+import type { SplitProposal } from '$models/SplitProposal';
 
 type EventMap = {
 	[K in Event['type']]: Extract<Event, { type: K }>['payload'];
@@ -32,8 +39,19 @@ type CommandArgs<C extends Command> = Commands[C][0];
 type CommandReturn<C extends Command> = Commands[C][1];
 
 export interface Commands {
-	get_songs: [{ url: string }, Song[]];
-	request_download: [{ song: Song }, void];
+	get_songs: [{ url: string }, Resolution];
+	request_download: [{ song: Song; job: JobTrack | null; jobAlbum: JobAlbum | null }, void];
+	remove_from_queue: [{ songs: Song[]; tracks: JobTrack[] }, void];
+	clear_queue: [{ songs: Song[]; tracks: JobTrack[] }, void];
+	resume_downloads: [NoParams, void];
+	clear_metadata_cache: [NoParams, void];
+	source_status: [NoParams, Record<SourceDownloader, SourceStatus>];
+	diagnostics: [NoParams, Diagnostics];
+	propose_split: [{ path: string }, SplitProposal];
+	split_download: [
+		{ path: string; song: Song; proposal: SplitProposal; tracklist: string },
+		string[],
+	];
 	get_config: [NoParams, Config];
 	update_config: [{ config: Config }, Config];
 }
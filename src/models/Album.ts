@@ -1,3 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type Album = { title: string, cover_url: string, };
+export type Album = { title: string, cover_url: string, 
+/**
+ * Main artist of the album, which may differ from the one of its songs.
+ */
+artist: string | null, total_tracks: number | null, genres: Array<string>, label: string | null, };
\ No newline at end of file
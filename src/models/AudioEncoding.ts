@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Encoder settings of Youtube downloads, each left to the encoder when unset.
+ */
+export type AudioEncoding = { 
+/**
+ * Constant bitrate, in kbps. Ignored by lossless formats.
+ */
+bitrate: number | null, 
+/**
+ * Variable bitrate quality, from 0 (best) to 9, used instead of the
+ * bitrate. Only MP3 and OGG support it.
+ */
+vbrQuality: number | null, 
+/**
+ * Sample rate, in Hz.
+ */
+sampleRate: number | null, 
+/**
+ * Number of channels.
+ */
+channels: number | null, };
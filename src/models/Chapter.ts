@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A chapter of a Youtube video, in seconds.
+ */
+export type Chapter = { title: string, start: number, end: number, };
\ No newline at end of file
@@ -1,3 +1,132 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AudioEncoding } from "./AudioEncoding";
 
-export type Config = { youtubeFormat: "mp3" | "webm" | "wav" | "ogg", };
+export type Config = { youtubeFormat: "mp3" | "webm" | "wav" | "ogg" | "flac" | "m4a" | "opus" | "original", youtubeEncoding: AudioEncoding, 
+/**
+ * Width and height of the covers embedded in Youtube downloads, in pixels.
+ */
+coverSize: number, 
+/**
+ * Also save the cover next to the downloaded file, named after it.
+ */
+saveCoverFile: boolean, 
+/**
+ * Split Youtube videos with chapters into one song per chapter.
+ */
+splitChapters: boolean, playlistTag: "album" | "grouping", loudness: { enabled: boolean, 
+/**
+ * Integrated loudness to reach, in LUFS.
+ */
+targetLufs: number, 
+/**
+ * Maximum true peak, in dBTP.
+ */
+truePeak: number, 
+/**
+ * Also normalise Deezer downloads, which are otherwise left as is.
+ */
+applyToDeezer: boolean, }, silenceTrimming: { enabled: boolean, 
+/**
+ * Volume under which audio is considered silent, in dB.
+ */
+thresholdDb: number, 
+/**
+ * Shortest silence that gets trimmed, in seconds.
+ */
+minDuration: number, 
+/**
+ * Also trim Deezer downloads, which are otherwise left as is.
+ */
+applyToDeezer: boolean, }, silenceSplitting: { enabled: boolean, 
+/**
+ * Volume under which audio is considered silent, in dB.
+ */
+thresholdDb: number, 
+/**
+ * Shortest silence considered a gap between two tracks, in seconds.
+ */
+minDuration: number, }, lyrics: { enabled: boolean, 
+/**
+ * Also save synced lyrics as an `.lrc` file next to the downloaded file.
+ */
+writeLrc: boolean, }, albumFolders: { 
+/**
+ * Download albums into their own folder, with their cover and an `.m3u8`
+ * playlist once every song is done.
+ */
+enabled: boolean, 
+/**
+ * Also save the resolved metadata as `album.json` in the folder.
+ */
+writeMetadata: boolean, }, verification: { enabled: boolean, 
+/**
+ * Largest accepted difference between the durations of the file and the
+ * song, in seconds.
+ */
+durationTolerance: number, 
+/**
+ * Number of times an incomplete download is attempted again.
+ */
+retries: number, }, 
+/**
+ * Number of Deezer songs downloaded at the same time.
+ */
+deezerThreads: number, 
+/**
+ * Number of Youtube songs downloaded at the same time.
+ */
+youtubeThreads: number, 
+/**
+ * Maximum number of songs downloaded at the same time, all sources combined.
+ */
+maxThreads: number, 
+/**
+ * Free space to keep in the download directory, in MB. Downloads pause
+ * instead of going below it, 0 never checks.
+ */
+minFreeSpace: number, metadataCache: { enabled: boolean, 
+/**
+ * Time entries are kept for, in hours.
+ */
+ttl: number, 
+/**
+ * Size the cache is kept under, in MB.
+ */
+maxSize: number, }, bandwidth: { 
+/**
+ * Rate of every download combined.
+ */
+global: number | null, deezer: number | null, youtube: number | null, 
+/**
+ * Hours the limits apply in, the whole day when unset.
+ */
+schedule: { start: string, end: string, } | null, }, proxy: { 
+/**
+ * URL of the proxy, such as `http://proxy:3128` or `socks5://proxy:1080`.
+ * Requests are sent directly when unset.
+ */
+url: string | null, username: string | null, password: string | null, 
+/**
+ * Hosts reached without the proxy, such as `localhost` or `.example.com`.
+ */
+noProxy: Array<string>, }, http: { 
+/**
+ * Time to wait for a connection to a server, in seconds.
+ */
+connectTimeout: number, 
+/**
+ * Time to wait for a server to send anything, in seconds.
+ */
+readTimeout: number, 
+/**
+ * Sent with every request, the client default when empty.
+ */
+userAgent: string, 
+/**
+ * Sent with every request.
+ */
+headers: Array<{ name: string, value: string, }>, }, 
+/**
+ * Directory containing ffmpeg and ffprobe, looked up in the `PATH` when unset.
+ */
+ffmpegDirectory: string | null, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Audio quality of Deezer downloads, from lowest to highest.
+ */
+export type DeezerQuality = "mp3_128" | "mp3_320" | "flac";
@@ -0,0 +1,15 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DownloadDirectory } from "./DownloadDirectory";
+import type { EncoderSupport } from "./EncoderSupport";
+import type { SourceCheck } from "./SourceCheck";
+import type { SourceDownloader } from "./SourceDownloader";
+import type { Tool } from "./Tool";
+
+/**
+ * State of everything downloads depend on.
+ */
+export type Diagnostics = { ffmpeg: Tool, ffprobe: Tool, 
+/**
+ * Encoders needed to transcode Youtube downloads.
+ */
+encoders: Array<EncoderSupport>, downloadDirectory: DownloadDirectory, sources: { [key in SourceDownloader]?: SourceCheck }, };
\ No newline at end of file
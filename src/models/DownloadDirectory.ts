@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type DownloadDirectory = { path: string | null, writable: boolean, 
+/**
+ * Why the directory is not writable.
+ */
+error: string | null, };
\ No newline at end of file
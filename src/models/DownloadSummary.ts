@@ -0,0 +1,28 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DeezerQuality } from "./DeezerQuality";
+import type { TrimmedSilence } from "./TrimmedSilence";
+
+/**
+ * What was written by a successful download.
+ */
+export type DownloadSummary = { path: string, 
+/**
+ * Format of the written file, as its extension.
+ */
+format: string, 
+/**
+ * Quality the song was downloaded in, for sources that offer a choice.
+ */
+quality: DeezerQuality | null, 
+/**
+ * Silence removed from the file, if trimming was enabled.
+ */
+trimmed: TrimmedSilence | null, 
+/**
+ * Lyrics were found and added to the file.
+ */
+lyrics: boolean, 
+/**
+ * Problems that did not fail the download.
+ */
+warnings: Array<string>, };
@@ -1,4 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DownloadSummary } from "./DownloadSummary";
 import type { Song } from "./Song";
+import type { SourceDownloader } from "./SourceDownloader";
+import type { SourceStatus } from "./SourceStatus";
 
-export type Event = { "type": "waiting", "payload": Song } | { "type": "start", "payload": Song } | { "type": "finish", "payload": Song } | { "type": "download_error", "payload": [Song, string] } | { "type": "remove_from_queue", "payload": Song };
+export type Event = { "type": "waiting", "payload": Song } | { "type": "start", "payload": Song } | { "type": "finish", "payload": [Song, DownloadSummary] } | { "type": "download_error", "payload": [Song, string] } | { "type": "remove_from_queue", "payload": Song } | { "type": "source_status", "payload": [SourceDownloader, SourceStatus] } | { "type": "paused", "payload": string };
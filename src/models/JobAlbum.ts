@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Album } from "./Album";
+import type { Song } from "./Song";
+
+/**
+ * Album of a job along with its songs, in order, from which the job starts
+ * when the first of them is requested.
+ */
+export type JobAlbum = { album: Album, songs: Array<Song>, };
\ No newline at end of file
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Place of a song in an album job.
+ */
+export type JobTrack = { job: number, 
+/**
+ * Position of the song in the album, from 0.
+ */
+index: number, };
\ No newline at end of file
@@ -0,0 +1,18 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Place of a song in the playlist it was resolved from.
+ */
+export type PlaylistEntry = { title: string, 
+/**
+ * Channel or user the playlist belongs to.
+ */
+owner: string | null, cover_url: string, 
+/**
+ * Position of the song in the playlist, from 1.
+ */
+position: number, 
+/**
+ * Number of songs in the playlist.
+ */
+total: number, };
\ No newline at end of file
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Tag the name of a playlist is written in, for songs resolved from one.
+ */
+export type PlaylistTag = "album" | "grouping";
\ No newline at end of file
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A release date, as precise as the source knows it.
+ */
+export type ReleaseDate = { year: number, month: number | null, day: number | null, };
\ No newline at end of file
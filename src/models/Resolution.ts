@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Album } from "./Album";
+import type { Song } from "./Song";
+import type { UnresolvedSong } from "./UnresolvedSong";
+
+/**
+ * Every song an id resolved to, along with the ones that failed.
+ */
+export type Resolution = { songs: Array<Song>, unresolved: Array<UnresolvedSong>, 
+/**
+ * Album or playlist the songs are part of, if the id refers to one.
+ */
+album: Album | null, 
+/**
+ * Album job the songs are downloaded as, see [crate::albums].
+ */
+job: number | null, };
\ No newline at end of file
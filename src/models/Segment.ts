@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Part of a video a song is cut from, in seconds.
+ */
+export type Segment = { start: number, end: number, 
+/**
+ * Position of the song in the video, from 0.
+ */
+index: number, 
+/**
+ * Number of songs the video is split into.
+ */
+count: number, };
\ No newline at end of file
@@ -1,4 +1,36 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Album } from "./Album";
+import type { Chapter } from "./Chapter";
+import type { PlaylistEntry } from "./PlaylistEntry";
+import type { ReleaseDate } from "./ReleaseDate";
+import type { Segment } from "./Segment";
 
-export type Song = { source: "youtube" | "deezer", id: string, title: string, album: Album, artist: string, release_date: string, };
+export type Song = { source: "youtube" | "deezer", id: string, title: string, album: Album, artist: string, 
+/**
+ * Every artist of the song, including the main one.
+ */
+contributors: Array<string>, release_date: ReleaseDate | null, 
+/**
+ * Duration, in seconds.
+ */
+duration: number | null, 
+/**
+ * Position of the song in its album or video.
+ */
+track_number: number | null, disc_number: number | null, isrc: string | null, explicit: boolean, 
+/**
+ * Playlist the song was resolved from.
+ */
+playlist: PlaylistEntry | null, 
+/**
+ * Grouping tag, such as the playlist the song is part of.
+ */
+grouping: string | null, 
+/**
+ * Chapters of the video, for Youtube songs.
+ */
+chapters: Array<Chapter>, 
+/**
+ * Part of the video the song is cut from, for songs split from chapters.
+ */
+segment: Segment | null, };
\ No newline at end of file
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SourceStatus } from "./SourceStatus";
+
+export type SourceCheck = { status: SourceStatus, 
+/**
+ * Why the source is unable to download songs.
+ */
+error: string | null, };
\ No newline at end of file
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Chapter } from "./Chapter";
+
+/**
+ * Tracks found in a file from its silences.
+ */
+export type SplitProposal = { 
+/**
+ * Duration of the whole file, in seconds.
+ */
+duration: number, tracks: Array<Chapter>, };
\ No newline at end of file
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * An external program, when it was found.
+ */
+export type Tool = { path: string | null, 
+/**
+ * First line of its version output.
+ */
+version: string | null, };
\ No newline at end of file
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Silence removed from the start and end of a file, in seconds.
+ */
+export type TrimmedSilence = { leading: number, trailing: number, };
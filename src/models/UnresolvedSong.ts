@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A song that was found but could not be resolved.
+ */
+export type UnresolvedSong = { id: string, title: string, reason: string, };
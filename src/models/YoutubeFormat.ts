@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type YoutubeFormat = "mp3" | "webm" | "wav" | "ogg" | "flac" | "m4a" | "opus" | "original";